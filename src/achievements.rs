@@ -3,12 +3,24 @@ use bevy::{
     prelude::*,
     sprite::Anchor,
     text::{BreakLineOn, Text2dBounds},
-    utils::Instant,
 };
 use bevy_hanabi::prelude::*;
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 
+use crate::{
+    announcements::Announcement,
+    audio::AudioCategory,
+    localization::Localization,
+    marquee::Marquee,
+    settings::{Settings, ToastPosition},
+};
+
+const ACHIEVEMENT_CARD_WIDTH: f32 = 300.0;
 const ACHIEVEMENT_CARD_HEIGHT: f32 = 100.0;
+/// Seconds a card stays on screen
+const ACHIEVEMENT_LIFETIME: f32 = 5.0;
+/// Seconds a new card takes to slide in and push the older ones up
+const ACHIEVEMENT_SLIDE_DURATION: f32 = 1.0;
 
 #[derive(Resource)]
 pub struct AchievementStyle {
@@ -19,7 +31,9 @@ pub struct AchievementStyle {
 
 #[derive(Component)]
 pub struct Achievement {
-    spawn_time: Instant,
+    /// Ticked with the game time, so the cards freeze while the game is paused and don't jump
+    /// ahead after the window was minimized
+    lifetime: Timer,
     index: usize,
 }
 
@@ -35,6 +49,19 @@ pub struct AchievementQueue {
     pub was_dimmer_used: bool,
     pub was_achievement_achieved: bool,
     pub time_flies_achieved: bool,
+    /// Achievements queued through [`AchievementQueue::unlock`]
+    pub unlocked: HashSet<String>,
+}
+
+impl AchievementQueue {
+    /// Queues the achievement unless it was already unlocked.
+    pub fn unlock(&mut self, text: &str) {
+        if self.unlocked.insert(text.to_string()) {
+            self.queue.push_back(AchievementToBeAdded {
+                text: text.to_string(),
+            });
+        }
+    }
 }
 
 pub fn setup_achievements(
@@ -94,70 +121,121 @@ pub fn setup_achievements(
     });
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn achievement_update(
     mut commands: Commands,
     achievement_style: Res<AchievementStyle>,
     query_ortho: Query<&OrthographicProjection>,
     mut achievement_queue: ResMut<AchievementQueue>,
-    mut achievements: Query<(&mut Transform, &Achievement, Entity)>,
+    mut achievements: Query<(&mut Transform, &mut Achievement, Entity)>,
+    mut marquee: ResMut<Marquee>,
+    settings: Res<Settings>,
+    localization: Res<Localization>,
+    time: Res<Time>,
+    mut announcements: EventWriter<Announcement>,
 ) {
     let mut shortest_lifetime = None;
-    for (_, achievement, entity) in achievements.iter_mut() {
-        let age = achievement.spawn_time.elapsed().as_secs_f32();
-        if age > 5.0 {
+    for (_, mut achievement, entity) in achievements.iter_mut() {
+        if achievement.lifetime.tick(time.delta()).finished() {
             commands.entity(entity).despawn_recursive();
         }
         if achievement.index == achievement_queue.num_achieved_achievements {
-            shortest_lifetime = Some(age);
+            shortest_lifetime = Some(achievement.lifetime.elapsed_secs());
         }
     }
     let ortho = query_ortho.single();
+    let card_size = achievement_card_size(&settings);
 
-    let lowest_stack_position = shortest_lifetime.map_or(0.0, |t| ((t - 1.0) / 1.0).min(0.0));
+    // With reduced motion the cards appear right at their place instead of sliding in
+    let mut lowest_stack_position = match shortest_lifetime {
+        Some(t) if !settings.reduced_motion => {
+            ((t - ACHIEVEMENT_SLIDE_DURATION) / ACHIEVEMENT_SLIDE_DURATION).min(0.0)
+        }
+        _ => 0.0,
+    };
+    let new_card = if lowest_stack_position >= 0.0 {
+        achievement_queue.queue.pop_front()
+    } else {
+        None
+    };
+    if new_card.is_some() {
+        // Counted before placing the older cards, so they make room in the same frame
+        achievement_queue.num_achieved_achievements += 1;
+        if !settings.reduced_motion {
+            lowest_stack_position = -1.0;
+        }
+    }
     for (mut transform, achievement, _) in achievements.iter_mut() {
         let stack_position = lowest_stack_position
             + achievement_queue.num_achieved_achievements as f32
             - achievement.index as f32;
-        transform.translation = achievement_position(ortho.area, stack_position);
+        transform.translation = achievement_position(
+            ortho.area,
+            settings.toast_position,
+            card_size,
+            stack_position,
+        );
     }
 
-    if lowest_stack_position >= 0.0 {
-        if let Some(event) = achievement_queue.queue.pop_front() {
-            if !achievement_queue.was_achievement_achieved {
-                achievement_queue.was_achievement_achieved = true;
-                achievement_queue.queue.push_back(AchievementToBeAdded {
-                    text: "Got it!".to_string(),
-                });
-            }
-
-            achievement_queue.num_achieved_achievements += 1;
-            spawn_achievement(
-                &mut commands,
-                achievement_style.as_ref(),
-                ortho.area,
-                achievement_queue.num_achieved_achievements,
-                &event.text,
-            );
+    if let Some(event) = new_card {
+        if !achievement_queue.was_achievement_achieved {
+            achievement_queue.was_achievement_achieved = true;
+            achievement_queue.unlock("Got it!");
         }
+
+        let text = localization.achievement(&event.text);
+        marquee.push(text);
+        announcements.send(Announcement(
+            localization.format("announcement.achievement", &[("name", &text)]),
+        ));
+        spawn_achievement(
+            &mut commands,
+            achievement_style.as_ref(),
+            ortho.area,
+            &settings,
+            achievement_queue.num_achieved_achievements,
+            text,
+        );
     }
 }
 
-fn achievement_position(screen_area: Rect, stack_position: f32) -> Vec3 {
-    vec3(
-        screen_area.max.x,
-        screen_area.min.y + stack_position * ACHIEVEMENT_CARD_HEIGHT,
-        0.0,
-    )
+fn achievement_card_size(settings: &Settings) -> Vec2 {
+    Vec2::new(ACHIEVEMENT_CARD_WIDTH, ACHIEVEMENT_CARD_HEIGHT) * settings.toast_scale
+}
+
+/// Bottom right corner of a card. Cards stack up from the chosen corner of the screen.
+fn achievement_position(
+    screen_area: Rect,
+    toast_position: ToastPosition,
+    card_size: Vec2,
+    stack_position: f32,
+) -> Vec3 {
+    let x = if toast_position.is_left() {
+        screen_area.min.x + card_size.x
+    } else {
+        screen_area.max.x
+    };
+    let y = if toast_position.is_top() {
+        screen_area.max.y - (stack_position + 1.0) * card_size.y
+    } else {
+        screen_area.min.y + stack_position * card_size.y
+    };
+    vec3(x, y, 0.0)
 }
 
 fn spawn_achievement(
     commands: &mut Commands,
     achievement_style: &AchievementStyle,
     screen_area: Rect,
+    settings: &Settings,
     achievement_index: usize,
     text: &str,
 ) {
-    let box_size = Vec2::new(300.0, ACHIEVEMENT_CARD_HEIGHT);
+    let box_size = achievement_card_size(settings);
+    let text_style = TextStyle {
+        font_size: achievement_style.text_style.font_size * settings.toast_scale,
+        ..achievement_style.text_style.clone()
+    };
     commands
         .spawn(SpriteBundle {
             sprite: Sprite {
@@ -166,16 +244,18 @@ fn spawn_achievement(
                 anchor: Anchor::BottomRight,
                 ..default()
             },
-            transform: Transform::from_translation(achievement_position(screen_area, -1.0)),
+            transform: Transform::from_translation(achievement_position(
+                screen_area,
+                settings.toast_position,
+                box_size,
+                if settings.reduced_motion { 0.0 } else { -1.0 },
+            )),
             ..default()
         })
         .with_children(|builder| {
             builder.spawn(Text2dBundle {
                 text: Text {
-                    sections: vec![TextSection::new(
-                        text.to_string(),
-                        achievement_style.text_style.clone(),
-                    )],
+                    sections: vec![TextSection::new(text.to_string(), text_style)],
                     alignment: TextAlignment::Left,
                     linebreak_behavior: BreakLineOn::WordBoundary,
                 },
@@ -187,6 +267,9 @@ fn spawn_achievement(
                 transform: Transform::from_xyz(-box_size.x * 0.5, box_size.y * 0.5, 1.0),
                 ..default()
             });
+            if settings.reduced_motion {
+                return;
+            }
             builder
                 .spawn(ParticleEffectBundle {
                     effect: ParticleEffect::new(achievement_style.particle_style.clone()),
@@ -196,12 +279,145 @@ fn spawn_achievement(
                 .insert(Name::new("effect:2d"));
         })
         .insert(Achievement {
-            spawn_time: Instant::now(),
+            lifetime: Timer::from_seconds(ACHIEVEMENT_LIFETIME, TimerMode::Once),
             index: achievement_index,
         });
 
     commands.spawn(AudioBundle {
         source: achievement_style.sound.clone(),
-        settings: PlaybackSettings::DESPAWN,
+        settings: AudioCategory::Sfx.once(settings),
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    const FRAME_TIME: Duration = Duration::from_millis(100);
+    const SCREEN_AREA: Rect = Rect {
+        min: Vec2::new(-640.0, -360.0),
+        max: Vec2::new(640.0, 360.0),
+    };
+
+    /// Runs [`achievement_update`] on its own, with game time advancing by [`FRAME_TIME`] per
+    /// update. The first update doesn't advance it.
+    fn toast_app(settings: Settings, achievements: &[&str]) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME_TIME))
+            .insert_resource(AchievementStyle {
+                text_style: default(),
+                particle_style: default(),
+                sound: default(),
+            })
+            .insert_resource(settings)
+            .insert_resource(Localization::new("en"))
+            .init_resource::<Marquee>()
+            .add_event::<Announcement>()
+            .add_systems(Update, achievement_update);
+        let mut queue = AchievementQueue {
+            // Keeps "Got it!" out of the queue
+            was_achievement_achieved: true,
+            ..default()
+        };
+        for achievement in achievements {
+            queue.unlock(achievement);
+        }
+        app.insert_resource(queue);
+        app.world.spawn(OrthographicProjection {
+            area: SCREEN_AREA,
+            ..default()
+        });
+        app
+    }
+
+    fn run(app: &mut App, updates: usize) {
+        for _ in 0..updates {
+            app.update();
+        }
+    }
+
+    /// Position of every card, oldest first.
+    fn card_positions(app: &mut App) -> Vec<Vec2> {
+        let mut cards = app
+            .world
+            .query::<(&Achievement, &Transform)>()
+            .iter(&app.world)
+            .map(|(achievement, transform)| (achievement.index, transform.translation.truncate()))
+            .collect::<Vec<_>>();
+        cards.sort_by_key(|(index, _)| *index);
+        cards.into_iter().map(|(_, position)| position).collect()
+    }
+
+    fn assert_positions(app: &mut App, expected: &[Vec2]) {
+        let positions = card_positions(app);
+        assert_eq!(positions.len(), expected.len(), "{positions:?}");
+        for (position, expected) in positions.iter().zip(expected) {
+            assert!(
+                position.distance(*expected) < 1e-3,
+                "{position} instead of {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn cards_slide_in_from_below() {
+        let mut app = toast_app(Settings::default(), &["Lights on"]);
+        run(&mut app, 1);
+        assert_positions(&mut app, &[Vec2::new(640.0, -460.0)]);
+        run(&mut app, 5);
+        assert_positions(&mut app, &[Vec2::new(640.0, -410.0)]);
+        run(&mut app, 5);
+        assert_positions(&mut app, &[Vec2::new(640.0, -360.0)]);
+        run(&mut app, 10);
+        assert_positions(&mut app, &[Vec2::new(640.0, -360.0)]);
+    }
+
+    #[test]
+    fn new_cards_push_older_ones_up() {
+        let mut app = toast_app(Settings::default(), &["Lights on", "Lights off"]);
+        // The second card waits until the first one has slid in
+        run(&mut app, 10);
+        assert_eq!(card_positions(&mut app).len(), 1);
+        run(&mut app, 2);
+        assert_positions(
+            &mut app,
+            &[Vec2::new(640.0, -350.0), Vec2::new(640.0, -450.0)],
+        );
+        run(&mut app, 9);
+        assert_positions(
+            &mut app,
+            &[Vec2::new(640.0, -260.0), Vec2::new(640.0, -360.0)],
+        );
+    }
+
+    #[test]
+    fn cards_despawn_after_their_lifetime() {
+        let mut app = toast_app(Settings::default(), &["Lights on"]);
+        let lifetime_updates = (ACHIEVEMENT_LIFETIME / FRAME_TIME.as_secs_f32()).round() as usize;
+        run(&mut app, lifetime_updates);
+        assert_eq!(card_positions(&mut app).len(), 1);
+        run(&mut app, 1);
+        assert!(card_positions(&mut app).is_empty());
+    }
+
+    #[test]
+    fn reduced_motion_cards_stack_from_the_top_left() {
+        let settings = Settings {
+            toast_position: ToastPosition::TopLeft,
+            toast_scale: 0.5,
+            reduced_motion: true,
+            ..default()
+        };
+        let mut app = toast_app(settings, &["Lights on", "Lights off"]);
+        run(&mut app, 1);
+        assert_positions(&mut app, &[Vec2::new(-490.0, 310.0)]);
+        run(&mut app, 1);
+        assert_positions(
+            &mut app,
+            &[Vec2::new(-490.0, 260.0), Vec2::new(-490.0, 310.0)],
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use bevy::prelude::*;
+use std::io::{self, Write};
+
+use crate::{localization::Localization, settings::Settings, Score};
+
+/// Seconds the score has to stay unchanged before it is announced, so toggling doesn't flood
+/// the output
+const SCORE_ANNOUNCEMENT_DELAY: f32 = 2.0;
+
+/// Plain text for screen readers and other assistive tools.
+#[derive(Event)]
+pub struct Announcement(pub String);
+
+/// Hook the announcements are written to when they are enabled in the [`Settings`], one per
+/// line. Standard output by default, so a screen reader attached to the terminal reads them.
+#[derive(Resource)]
+pub struct AnnouncementOutput(pub Box<dyn Write + Send + Sync>);
+
+impl Default for AnnouncementOutput {
+    fn default() -> Self {
+        Self(Box::new(io::stdout()))
+    }
+}
+
+pub fn announcement_output(
+    settings: Res<Settings>,
+    mut announcements: EventReader<Announcement>,
+    mut output: ResMut<AnnouncementOutput>,
+) {
+    if !settings.announcements {
+        announcements.clear();
+        return;
+    }
+    for Announcement(text) in announcements.read() {
+        let result = writeln!(output.0, "{text}").and_then(|_| output.0.flush());
+        if let Err(err) = result {
+            warn!("Could not write announcement: {err}");
+        }
+    }
+}
+
+/// Announces the score once it has settled.
+pub fn score_announcement_update(
+    score: Res<Score>,
+    time: Res<Time>,
+    localization: Res<Localization>,
+    mut announcements: EventWriter<Announcement>,
+    mut announced: Local<Option<u64>>,
+    mut settled_for: Local<f32>,
+) {
+    if score.is_changed() {
+        *settled_for = 0.0;
+        return;
+    }
+    *settled_for += time.delta_seconds();
+    if *settled_for >= SCORE_ANNOUNCEMENT_DELAY && *announced != Some(score.value) {
+        *announced = Some(score.value);
+        announcements.send(Announcement(
+            localization.format("announcement.score", &[("score", &score.value)]),
+        ));
+    }
+}
@@ -0,0 +1,455 @@
+use bevy::{
+    audio::{AudioSinkPlayback, Decodable, Source, SpatialScale, Volume},
+    prelude::*,
+    utils::Duration,
+};
+use rand::{Rng, SeedableRng};
+use std::{f32::consts::TAU, sync::Arc};
+
+use crate::{
+    replay::PlayerAction, replay::PlayerInput, settings::Settings, Clock, ColorTemperature,
+    LightSwitch, WallClock,
+};
+
+/// Sample rate of the generated sounds
+const SAMPLE_RATE: u32 = 22050;
+const HUM_GAIN: f32 = 0.15;
+const MUSIC_GAIN: f32 = 0.4;
+/// Seconds a music track plays before the next one fades in
+const MUSIC_TRACK_DURATION: f32 = 64.0;
+const MUSIC_CROSSFADE_DURATION: f32 = 8.0;
+/// Spatial sounds fade with the squared distance. Scaled down, the scene is heard at full volume
+/// from about the default camera position.
+pub const SPATIAL_SCALE: f32 = 0.25;
+/// Distance between the ears of the listener, in scene units
+pub const EAR_GAP: f32 = 2.0;
+/// Seconds between two strokes of the hourly chime
+const CHIME_INTERVAL: f32 = 2.0;
+
+/// Mixer channel of a sound, each with its own volume in the [`Settings`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AudioCategory {
+    Sfx,
+    Ambience,
+    Music,
+}
+
+impl AudioCategory {
+    pub fn volume(self, settings: &Settings) -> f32 {
+        match self {
+            AudioCategory::Sfx => settings.sfx_volume,
+            AudioCategory::Ambience => settings.ambience_volume,
+            AudioCategory::Music => settings.music_volume,
+        }
+    }
+
+    /// Playback settings for a one-shot sound of this category.
+    pub fn once(self, settings: &Settings) -> PlaybackSettings {
+        PlaybackSettings::DESPAWN.with_volume(Volume::new_relative(self.volume(settings)))
+    }
+
+    /// Like [`AudioCategory::once`], but heard from where it is emitted.
+    pub fn once_at(self, settings: &Settings, position: Vec3) -> impl Bundle {
+        (
+            self.once(settings).with_spatial(true),
+            // Spatial sinks are set up before the transforms propagate
+            Transform::from_translation(position),
+            GlobalTransform::from_translation(position),
+        )
+    }
+}
+
+/// Mono sound generated at startup instead of being loaded from a file.
+#[derive(Asset, TypePath, Clone)]
+pub struct SynthSound {
+    samples: Arc<[f32]>,
+}
+
+impl SynthSound {
+    /// Samples `wave` at the times of `duration` seconds of sound.
+    fn generate(duration: f32, mut wave: impl FnMut(f32) -> f32) -> Self {
+        let len = (duration * SAMPLE_RATE as f32) as usize;
+        Self {
+            samples: (0..len)
+                .map(|i| wave(i as f32 / SAMPLE_RATE as f32))
+                .collect(),
+        }
+    }
+
+    /// Mechanical switch click: a burst of noise ringing at `pitch`.
+    fn click(pitch: f32, seed: u64) -> Self {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        Self::generate(0.06, |t| {
+            let noise = rng.gen_range(-1.0..1.0);
+            let envelope = (-t * 120.0).exp();
+            envelope * (0.5 * noise + 0.5 * (TAU * pitch * t).sin())
+        })
+    }
+
+    fn detent_tick() -> Self {
+        Self::generate(0.015, |t| {
+            (-t * 400.0).exp() * 0.4 * (TAU * 3200.0 * t).sin()
+        })
+    }
+
+    /// Wooden knock of the clockwork, `pitch` telling tick from tock.
+    fn clock_tick(pitch: f32) -> Self {
+        Self::generate(0.04, |t| (-t * 150.0).exp() * 0.5 * (TAU * pitch * t).sin())
+    }
+
+    /// Bell stroke with the slightly inharmonic partials of a real bell.
+    fn chime() -> Self {
+        Self::generate(3.0, |t| {
+            [
+                (0.5, 0.4),
+                (1.0, 0.5),
+                (1.19, 0.3),
+                (1.5, 0.2),
+                (2.0, 0.2),
+                (2.74, 0.1),
+            ]
+            .into_iter()
+            .map(|(partial, amplitude)| {
+                // Higher partials die away faster
+                amplitude * (-t * 1.2 * partial).exp() * (TAU * 523.0 * partial * t).sin()
+            })
+            .sum::<f32>()
+                * 0.5
+        })
+    }
+
+    /// Mains hum with a few harmonics. One second holds whole periods, so it loops seamlessly.
+    fn hum() -> Self {
+        Self::generate(1.0, |t| {
+            [(100.0, 0.6), (200.0, 0.3), (300.0, 0.1)]
+                .into_iter()
+                .map(|(frequency, amplitude)| amplitude * (TAU * frequency * t).sin())
+                .sum()
+        })
+    }
+
+    /// Slow pad playing the chords, given as MIDI notes, for `chord_duration` seconds each.
+    /// Every chord swells in and out, so the track loops seamlessly.
+    fn pad(chords: &[[u8; 3]], chord_duration: f32) -> Self {
+        Self::generate(chords.len() as f32 * chord_duration, |t| {
+            let chord = chords[(t / chord_duration) as usize % chords.len()];
+            let envelope = (t / chord_duration * TAU / 2.0).sin().powi(2);
+            let tone: f32 = chord
+                .into_iter()
+                .map(|note| {
+                    let frequency = 440.0 * 2.0f32.powf((note as f32 - 69.0) / 12.0);
+                    (TAU * frequency * t).sin() + 0.3 * (TAU * 2.0 * frequency * t).sin()
+                })
+                .sum();
+            envelope * tone / 4.0
+        })
+    }
+}
+
+impl Decodable for SynthSound {
+    type DecoderItem = f32;
+    type Decoder = SynthDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        SynthDecoder {
+            samples: self.samples.clone(),
+            position: 0,
+        }
+    }
+}
+
+pub struct SynthDecoder {
+    samples: Arc<[f32]>,
+    position: usize,
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+#[derive(Resource)]
+pub struct Sounds {
+    pub switch_press: Handle<SynthSound>,
+    pub switch_release: Handle<SynthSound>,
+    pub dimmer_tick: Handle<SynthSound>,
+    pub clock_tick: Handle<SynthSound>,
+    pub clock_tock: Handle<SynthSound>,
+    pub chime: Handle<SynthSound>,
+}
+
+/// Looping sound whose volume the mixer sets every frame.
+#[derive(Component, Clone, Copy)]
+pub enum AudioLoop {
+    BulbHum,
+    /// Index of the track in the [`Music`] rotation
+    MusicTrack(usize),
+}
+
+impl AudioLoop {
+    fn category(self) -> AudioCategory {
+        match self {
+            AudioLoop::BulbHum => AudioCategory::Ambience,
+            AudioLoop::MusicTrack(_) => AudioCategory::Music,
+        }
+    }
+}
+
+/// Background music, fading from one track into the next.
+#[derive(Resource)]
+pub struct Music {
+    tracks: usize,
+    current: usize,
+    timer: Timer,
+    /// Progress from 0 to 1 of the fade into the next track, if one is running
+    crossfade: Option<f32>,
+}
+
+impl Music {
+    fn gain(&self, index: usize) -> f32 {
+        let fade = self.crossfade.unwrap_or(0.0);
+        if index == self.current {
+            1.0 - fade
+        } else if index == (self.current + 1) % self.tracks {
+            fade
+        } else {
+            0.0
+        }
+    }
+}
+
+pub fn setup_audio(
+    mut commands: Commands,
+    mut sounds: ResMut<Assets<SynthSound>>,
+    lights: Query<Entity, With<DirectionalLight>>,
+) {
+    commands.insert_resource(SpatialScale::new(SPATIAL_SCALE));
+    commands.insert_resource(Sounds {
+        switch_press: sounds.add(SynthSound::click(1800.0, 1)),
+        switch_release: sounds.add(SynthSound::click(2400.0, 2)),
+        dimmer_tick: sounds.add(SynthSound::detent_tick()),
+        clock_tick: sounds.add(SynthSound::clock_tick(1400.0)),
+        clock_tock: sounds.add(SynthSound::clock_tick(1000.0)),
+        chime: sounds.add(SynthSound::chime()),
+    });
+
+    // Loops start silent, the mixer brings them up
+    let silent_loop = PlaybackSettings::LOOP.with_volume(Volume::new_relative(0.0));
+    let hum = sounds.add(SynthSound::hum());
+    for light in lights.iter() {
+        commands.entity(light).with_children(|builder| {
+            builder.spawn((
+                AudioSourceBundle {
+                    source: hum.clone(),
+                    settings: silent_loop.with_spatial(true),
+                },
+                SpatialBundle::default(),
+                AudioLoop::BulbHum,
+            ));
+        });
+    }
+
+    // A minor - F major - C major - G major, and D minor - B flat major - F major - C major
+    let tracks = [
+        SynthSound::pad(
+            &[[57, 60, 64], [53, 57, 60], [48, 52, 55], [55, 59, 62]],
+            4.0,
+        ),
+        SynthSound::pad(
+            &[[50, 53, 57], [46, 50, 53], [53, 57, 60], [48, 52, 55]],
+            4.0,
+        ),
+    ];
+    let track_count = tracks.len();
+    for (index, track) in tracks.into_iter().enumerate() {
+        commands.spawn((
+            AudioSourceBundle {
+                source: sounds.add(track),
+                settings: silent_loop,
+            },
+            AudioLoop::MusicTrack(index),
+        ));
+    }
+    commands.insert_resource(Music {
+        tracks: track_count,
+        current: 0,
+        timer: Timer::from_seconds(MUSIC_TRACK_DURATION, TimerMode::Repeating),
+        crossfade: None,
+    });
+}
+
+/// Switch clicks and dimmer detent ticks.
+pub fn audio_effects_update(
+    mut commands: Commands,
+    sounds: Res<Sounds>,
+    settings: Res<Settings>,
+    player_input: Res<PlayerInput>,
+    switch: Query<&GlobalTransform, (With<LightSwitch>, Without<ColorTemperature>)>,
+    dimmer: Query<(&GlobalTransform, &ColorTemperature)>,
+    mut last_detent: Local<Option<i32>>,
+) {
+    let mut play = |source: &Handle<SynthSound>, position: Vec3| {
+        commands.spawn((
+            source.clone(),
+            AudioCategory::Sfx.once_at(&settings, position),
+        ));
+    };
+    let switch_position = switch
+        .get_single()
+        .map_or(Vec3::ZERO, |transform| transform.translation());
+    for action in &player_input.actions {
+        match action {
+            PlayerAction::SwitchPressed => play(&sounds.switch_press, switch_position),
+            PlayerAction::SwitchReleased => play(&sounds.switch_release, switch_position),
+            _ => {}
+        }
+    }
+
+    // One tick per detent passed, none when the dimmer is already at its end
+    let Ok((dimmer_transform, temperature)) = dimmer.get_single() else {
+        return;
+    };
+    let detent = (temperature.value / ColorTemperature::STEP).round() as i32;
+    if last_detent.is_some_and(|last| last != detent) {
+        play(&sounds.dimmer_tick, dimmer_transform.translation());
+    }
+    *last_detent = Some(detent);
+}
+
+/// Sets the volume of the looping sounds. Their sinks ignore [`GlobalVolume`] after they were
+/// created, so it is applied here as well.
+#[allow(clippy::too_many_arguments)]
+pub fn audio_mixer_update(
+    time: Res<Time<Real>>,
+    settings: Res<Settings>,
+    global_volume: Res<GlobalVolume>,
+    mut music: ResMut<Music>,
+    lights: Query<&DirectionalLight>,
+    temperature: Query<&ColorTemperature>,
+    loops: Query<(&AudioLoop, Option<&AudioSink>, Option<&SpatialAudioSink>)>,
+) {
+    if music.timer.tick(time.delta()).just_finished() {
+        music.crossfade = Some(0.0);
+    }
+    if let Some(fade) = music.crossfade {
+        let fade = fade + time.delta_seconds() / MUSIC_CROSSFADE_DURATION;
+        if fade >= 1.0 {
+            music.current = (music.current + 1) % music.tracks;
+            music.crossfade = None;
+        } else {
+            music.crossfade = Some(fade);
+        }
+    }
+
+    let light_on = lights.iter().any(|light| light.illuminance > 0.0);
+    let temperature = temperature.get_single().map_or(0.5, |t| t.value);
+    for (&audio_loop, sink, spatial_sink) in loops.iter() {
+        let sink: &dyn AudioSinkPlayback = match (sink, spatial_sink) {
+            (Some(sink), _) => sink,
+            (None, Some(sink)) => sink,
+            // Not playing yet
+            (None, None) => continue,
+        };
+        let gain = match audio_loop {
+            AudioLoop::BulbHum => {
+                // Warmer light hums lower
+                let speed = 0.85 + temperature * 0.3;
+                if sink.speed() != speed {
+                    sink.set_speed(speed);
+                }
+                if light_on {
+                    HUM_GAIN
+                } else {
+                    0.0
+                }
+            }
+            AudioLoop::MusicTrack(index) => MUSIC_GAIN * music.gain(index),
+        };
+        let volume = global_volume.volume.get() * audio_loop.category().volume(&settings) * gain;
+        if sink.volume() != volume {
+            sink.set_volume(volume);
+        }
+    }
+}
+
+/// Strokes of the hourly chime still to come
+#[derive(Default)]
+pub struct ClockChime {
+    strokes_left: u32,
+    timer: Timer,
+}
+
+/// Ticks on every second of the [`WallClock`] and strikes the hours.
+#[allow(clippy::too_many_arguments)]
+pub fn clock_sound_update(
+    mut commands: Commands,
+    // The clock keeps running while the game is paused
+    time: Res<Time<Real>>,
+    wall_clock: Res<WallClock>,
+    sounds: Res<Sounds>,
+    settings: Res<Settings>,
+    clock: Query<&GlobalTransform, With<Clock>>,
+    mut last_second: Local<Option<chrono::DateTime<chrono::Local>>>,
+    mut chime: Local<ClockChime>,
+) {
+    use chrono::prelude::*;
+
+    let now = wall_clock.now(&time).trunc_subsecs(0);
+    let Some(last) = last_second.replace(now) else {
+        return;
+    };
+    let Ok(clock) = clock.get_single() else {
+        return;
+    };
+    if !settings.clock_sound {
+        chime.strokes_left = 0;
+        return;
+    }
+    let mut play = |source: &Handle<SynthSound>| {
+        commands.spawn((
+            source.clone(),
+            AudioCategory::Ambience.once_at(&settings, clock.translation()),
+        ));
+    };
+
+    if now != last {
+        let tick_tock = [&sounds.clock_tick, &sounds.clock_tock];
+        play(tick_tock[now.second() as usize % 2]);
+        if now.hour() != last.hour() {
+            // The first stroke comes with the tick, the others follow at the interval
+            play(&sounds.chime);
+            chime.strokes_left = now.hour12().1 - 1;
+            chime.timer = Timer::from_seconds(CHIME_INTERVAL, TimerMode::Repeating);
+            return;
+        }
+    }
+    if chime.strokes_left > 0 && chime.timer.tick(time.delta()).just_finished() {
+        chime.strokes_left -= 1;
+        play(&sounds.chime);
+    }
+}
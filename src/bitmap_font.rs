@@ -0,0 +1,270 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use std::fmt;
+
+/// A pixel font with glyphs of a common height and variable width.
+/// See `wall_font.wallfont` for a description of the format.
+#[derive(Asset, TypePath, Debug)]
+pub struct BitmapFont {
+    pub height: usize,
+    pub spacing: i32,
+    glyphs: HashMap<char, BitmapGlyph>,
+    kerning: HashMap<(char, char), i32>,
+}
+
+#[derive(Debug)]
+pub struct BitmapGlyph {
+    pub width: usize,
+    /// Row-major, top row first
+    pixels: Vec<bool>,
+}
+
+#[derive(Debug)]
+pub enum BitmapFontError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for BitmapFontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitmapFontError::Io(err) => write!(f, "could not read bitmap font: {err}"),
+            BitmapFontError::Parse { line, message } => {
+                write!(f, "invalid bitmap font (line {line}): {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BitmapFontError {}
+
+impl From<std::io::Error> for BitmapFontError {
+    fn from(err: std::io::Error) -> Self {
+        BitmapFontError::Io(err)
+    }
+}
+
+impl BitmapGlyph {
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[x + y * self.width]
+    }
+}
+
+impl BitmapFont {
+    pub fn parse(source: &str) -> Result<Self, BitmapFontError> {
+        let mut height = None;
+        let mut spacing = 1;
+        let mut glyphs = HashMap::new();
+        let mut kerning = HashMap::new();
+
+        let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line));
+        while let Some((line_number, line)) = lines.next() {
+            let error = |message: &str| BitmapFontError::Parse {
+                line: line_number,
+                message: message.to_string(),
+            };
+
+            if let Some(header) = line.strip_prefix('[') {
+                let mut chars = header.chars();
+                let (Some(c), Some(']')) = (chars.next(), chars.next()) else {
+                    return Err(error("expected a glyph header like `[A]`"));
+                };
+                let Some(height) = height else {
+                    return Err(error("`height` must be set before the first glyph"));
+                };
+                let explicit_width = match chars.as_str().trim() {
+                    "" => None,
+                    width => Some(width.parse().map_err(|_| error("invalid glyph width"))?),
+                };
+
+                let mut rows = Vec::with_capacity(height);
+                for _ in 0..height {
+                    let Some((_, row)) = lines.next() else {
+                        return Err(error("glyph has fewer rows than `height`"));
+                    };
+                    rows.push(row);
+                }
+                let width = explicit_width
+                    .unwrap_or_else(|| rows.iter().map(|row| row.len()).max().unwrap_or(0));
+                let mut pixels = vec![false; width * height];
+                for (y, row) in rows.iter().enumerate() {
+                    for (x, pixel) in row.chars().enumerate() {
+                        match pixel {
+                            ' ' => {}
+                            '.' if x < width => pixels[x + y * width] = true,
+                            '.' => return Err(error("glyph row is wider than its width")),
+                            _ => return Err(error("glyph rows may only contain ' ' and '.'")),
+                        }
+                    }
+                }
+                glyphs.insert(c, BitmapGlyph { width, pixels });
+                continue;
+            }
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("height"), Some(value), None) => {
+                    height = Some(value.parse().map_err(|_| error("invalid height"))?);
+                }
+                (Some("spacing"), Some(value), None) => {
+                    spacing = value.parse().map_err(|_| error("invalid spacing"))?;
+                }
+                (Some("kern"), Some(pair), Some(value)) => {
+                    let mut pair = pair.chars();
+                    let (Some(left), Some(right), None) = (pair.next(), pair.next(), pair.next())
+                    else {
+                        return Err(error("kerning needs exactly two characters"));
+                    };
+                    let value = value.parse().map_err(|_| error("invalid kerning"))?;
+                    kerning.insert((left, right), value);
+                }
+                _ => return Err(error("unknown statement")),
+            }
+        }
+
+        Ok(Self {
+            height: height.unwrap_or(0),
+            spacing,
+            glyphs,
+            kerning,
+        })
+    }
+
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.glyphs.contains_key(&c)
+    }
+
+    /// Unknown characters fall back to `?`, if the font has one.
+    pub fn glyph(&self, c: char) -> Option<&BitmapGlyph> {
+        self.glyphs.get(&c).or_else(|| self.glyphs.get(&'?'))
+    }
+
+    pub fn kerning(&self, left: char, right: char) -> i32 {
+        self.kerning.get(&(left, right)).copied().unwrap_or(0)
+    }
+
+    /// Column of every glyph of `text`, relative to the first column.
+    fn layout<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (i32, &'a BitmapGlyph)> + 'a {
+        let mut cursor = 0;
+        let mut previous = None;
+        text.chars().filter_map(move |c| {
+            let glyph = self.glyph(c)?;
+            if let Some(previous) = previous {
+                cursor += self.spacing + self.kerning(previous, c);
+            }
+            let x = cursor;
+            cursor += glyph.width as i32;
+            previous = Some(c);
+            Some((x, glyph))
+        })
+    }
+
+    pub fn text_width(&self, text: &str) -> i32 {
+        self.layout(text)
+            .map(|(x, glyph)| x + glyph.width as i32)
+            .last()
+            .unwrap_or(0)
+    }
+
+    /// Calls `plot` with the glyph index, column and row (counted from the top) of every lit pixel.
+    pub fn render(&self, text: &str, mut plot: impl FnMut(usize, i32, i32)) {
+        for (index, (x, glyph)) in self.layout(text).enumerate() {
+            for glyph_y in 0..self.height {
+                for glyph_x in 0..glyph.width {
+                    if glyph.pixel(glyph_x, glyph_y) {
+                        plot(index, x + glyph_x as i32, glyph_y as i32);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BitmapFontLoader;
+
+impl AssetLoader for BitmapFontLoader {
+    type Asset = BitmapFont;
+    type Settings = ();
+    type Error = BitmapFontError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<BitmapFont, Self::Error>> {
+        Box::pin(async move {
+            let mut source = String::new();
+            reader.read_to_string(&mut source).await?;
+            BitmapFont::parse(&source)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["wallfont"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT: &str = "\
+height 2
+spacing 1
+kern AB -1
+
+[A]
+ .
+. .
+[B] 1
+.
+.
+[?]
+..
+ .
+";
+
+    #[test]
+    fn parses_glyphs_and_kerning() {
+        let font = BitmapFont::parse(FONT).unwrap();
+        assert_eq!(font.height, 2);
+        let a = font.glyph('A').unwrap();
+        assert_eq!(a.width, 3);
+        assert!(a.pixel(1, 0) && !a.pixel(0, 0) && a.pixel(2, 1));
+        assert_eq!(font.glyph('B').unwrap().width, 1);
+        assert_eq!(font.kerning('A', 'B'), -1);
+        assert_eq!(font.kerning('B', 'A'), 0);
+        // 3 + 1 - 1 + 1, then 1 + 2 for the fallback glyph of `C`
+        assert_eq!(font.text_width("AB"), 4);
+        assert_eq!(font.text_width("BC"), 4);
+    }
+
+    #[test]
+    fn wall_font_parses() {
+        let font = BitmapFont::parse(include_str!("wall_font.wallfont")).unwrap();
+        assert_eq!(font.height, 5);
+        assert!(('0'..='9').all(|c| font.has_glyph(c)));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error_line = |source: &str| match BitmapFont::parse(source) {
+            Err(BitmapFontError::Parse { line, .. }) => Some(line),
+            _ => None,
+        };
+        assert_eq!(error_line("[A]\n.\n"), Some(1));
+        assert_eq!(error_line("height 1\n[A] 1\n..\n"), Some(2));
+        assert_eq!(error_line("height 2\n[A]\n.\n"), Some(2));
+        assert_eq!(error_line("height 1\n\nkern ABC 1\n"), Some(3));
+        assert_eq!(error_line("height 1\nwidth 3\n"), Some(2));
+    }
+}
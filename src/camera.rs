@@ -0,0 +1,293 @@
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    math::vec2,
+    prelude::*,
+};
+use std::f32::consts::{PI, TAU};
+
+use crate::storage::{load_key_values, save_key_values};
+
+const BOOKMARKS_FILE: &str = "camera.txt";
+/// Radians per pixel of mouse movement
+const ORBIT_SPEED: f32 = 0.005;
+/// Scene units per pixel of mouse movement, at unit distance
+const PAN_SPEED: f32 = 0.002;
+/// Share of the distance per scroll line
+const ZOOM_SPEED: f32 = 0.1;
+const MIN_DISTANCE: f32 = 1.0;
+const MAX_DISTANCE: f32 = 15.0;
+/// Keeps the camera from flipping over the poles
+const MAX_PITCH: f32 = 1.5;
+/// Per second, how much of the remaining way a transition covers
+const TRANSITION_RATE: f32 = 6.0;
+const BOOKMARK_KEYS: [KeyCode; 4] = [KeyCode::F6, KeyCode::F7, KeyCode::F8, KeyCode::F9];
+/// Part of the wall that stays in view at any aspect ratio: the clock on the left, the switch in
+/// the middle and the score digits on the right
+const FRAMED_AREA: Rect = Rect {
+    min: Vec2::new(-2.1, -0.5),
+    max: Vec2::new(2.8, 1.5),
+};
+
+/// Camera position around a focus point.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CameraView {
+    pub focus: Vec3,
+    /// Rotation around the vertical axis, zero looking along -Z. Kept between 0 and 2π.
+    pub yaw: f32,
+    /// Rotation upwards, zero looking horizontally
+    pub pitch: f32,
+    pub distance: f32,
+}
+
+impl Default for CameraView {
+    /// The framing the scene was designed for, from `(-0.5, 1.0, 4.0)` towards the origin.
+    fn default() -> Self {
+        Self::looking_at(Vec3::new(-0.5, 1.0, 4.0), Vec3::ZERO)
+    }
+}
+
+impl CameraView {
+    fn looking_at(eye: Vec3, focus: Vec3) -> Self {
+        let offset = eye - focus;
+        Self {
+            focus,
+            yaw: offset.x.atan2(offset.z).rem_euclid(TAU),
+            pitch: (offset.y / offset.length()).asin(),
+            distance: offset.length(),
+        }
+    }
+
+    pub fn transform(&self) -> Transform {
+        let rotation = Quat::from_euler(EulerRot::YXZ, self.yaw, -self.pitch, 0.0);
+        Transform::from_translation(self.focus + rotation * Vec3::Z * self.distance)
+            .with_rotation(rotation)
+    }
+
+    /// Smallest distance at which the whole `area` on the wall plane is in view.
+    fn fitting_distance(&self, area: Rect, projection: &PerspectiveProjection) -> f32 {
+        let tan_y = (projection.fov * 0.5).tan();
+        let tan_x = tan_y * projection.aspect_ratio;
+        let to_view = self.transform().rotation.inverse();
+        [
+            area.min,
+            vec2(area.min.x, area.max.y),
+            vec2(area.max.x, area.min.y),
+            area.max,
+        ]
+        .into_iter()
+        .map(|corner| {
+            let corner = to_view * (corner.extend(0.0) - self.focus);
+            (corner.x.abs() / tan_x).max(corner.y.abs() / tan_y) + corner.z
+        })
+        .fold(0.0, f32::max)
+    }
+
+    /// Turn from this yaw to the target's the short way round, between -π and π.
+    fn yaw_to(&self, target: &Self) -> f32 {
+        (target.yaw - self.yaw + PI).rem_euclid(TAU) - PI
+    }
+
+    fn lerp(&self, target: &Self, t: f32) -> Self {
+        Self {
+            focus: self.focus.lerp(target.focus, t),
+            yaw: (self.yaw + self.yaw_to(target) * t).rem_euclid(TAU),
+            pitch: self.pitch + (target.pitch - self.pitch) * t,
+            distance: self.distance + (target.distance - self.distance) * t,
+        }
+    }
+
+    /// `focus.x focus.y focus.z yaw pitch distance`
+    fn parse(value: &str) -> Option<Self> {
+        let numbers = value
+            .split_whitespace()
+            .map(|number| number.parse::<f32>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let [x, y, z, yaw, pitch, distance] = numbers[..] else {
+            return None;
+        };
+        Some(Self {
+            focus: Vec3::new(x, y, z),
+            yaw: yaw.rem_euclid(TAU),
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+            distance: distance.clamp(MIN_DISTANCE, MAX_DISTANCE),
+        })
+    }
+
+    fn to_value(self) -> String {
+        let Vec3 { x, y, z } = self.focus;
+        format!("{x} {y} {z} {} {} {}", self.yaw, self.pitch, self.distance)
+    }
+}
+
+/// Lets the player orbit, pan and zoom the 3D camera.
+#[derive(Component, Default)]
+pub struct OrbitCamera {
+    pub view: CameraView,
+    /// View the camera is gliding to
+    pub target: Option<CameraView>,
+}
+
+/// Saved views, recalled with F6 to F9 and stored with Ctrl and the same key.
+#[derive(Resource, Default)]
+pub struct CameraBookmarks {
+    views: [Option<CameraView>; BOOKMARK_KEYS.len()],
+}
+
+impl CameraBookmarks {
+    fn load() -> Self {
+        let values = load_key_values(BOOKMARKS_FILE);
+        let mut bookmarks = Self::default();
+        for (i, view) in bookmarks.views.iter_mut().enumerate() {
+            *view = values
+                .get(&format!("bookmark.{i}"))
+                .and_then(|value| CameraView::parse(value));
+        }
+        bookmarks
+    }
+
+    fn save(&self) {
+        save_key_values(
+            BOOKMARKS_FILE,
+            self.views.iter().enumerate().filter_map(|(i, view)| {
+                view.map(|view| (format!("bookmark.{i}"), view.to_value()))
+            }),
+        );
+    }
+}
+
+/// Scrolling zooms the camera instead of turning the dimmer while this is held.
+pub fn zoom_modifier_pressed(keyboard_input: &Input<KeyCode>) -> bool {
+    keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+}
+
+pub fn setup_camera(mut commands: Commands) {
+    commands.insert_resource(CameraBookmarks::load());
+}
+
+pub fn camera_controls_update(
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut scroll_events: EventReader<MouseWheel>,
+    mut bookmarks: ResMut<CameraBookmarks>,
+    mut cameras: Query<&mut OrbitCamera>,
+) {
+    let Ok(mut camera) = cameras.get_single_mut() else {
+        return;
+    };
+    let motion = mouse_motion.read().map(|motion| motion.delta).sum::<Vec2>();
+    let scroll = scroll_events
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 100.0,
+        })
+        .sum::<f32>();
+
+    // Starting from where a transition is heading keeps the input from fighting it
+    let mut view = camera.target.unwrap_or(camera.view);
+    let mut moved = false;
+    if mouse_input.pressed(MouseButton::Right) && motion != Vec2::ZERO {
+        if keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            let rotation = view.transform().rotation;
+            let pan = rotation * Vec3::new(-motion.x, motion.y, 0.0);
+            view.focus += pan * PAN_SPEED * view.distance;
+        } else {
+            view.yaw = (view.yaw - motion.x * ORBIT_SPEED).rem_euclid(TAU);
+            view.pitch = (view.pitch + motion.y * ORBIT_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        moved = true;
+    }
+    if zoom_modifier_pressed(&keyboard_input) && scroll != 0.0 {
+        view.distance =
+            (view.distance * (1.0 - scroll * ZOOM_SPEED)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+        moved = true;
+    }
+    if moved {
+        // Direct control, no easing
+        camera.view = view;
+        camera.target = None;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Home) {
+        camera.target = Some(CameraView::default());
+    }
+    for (i, key) in BOOKMARK_KEYS.into_iter().enumerate() {
+        if !keyboard_input.just_pressed(key) {
+            continue;
+        }
+        if zoom_modifier_pressed(&keyboard_input) {
+            bookmarks.views[i] = Some(camera.target.unwrap_or(camera.view));
+            bookmarks.save();
+            info!("Saved camera bookmark {}", i + 1);
+        } else if let Some(view) = bookmarks.views[i] {
+            camera.target = Some(view);
+        }
+    }
+}
+
+/// Glides towards the target view and moves the camera. Uses real time, so it works while the
+/// game is paused.
+///
+/// Windows narrower than the default view was made for move the camera back, so that the
+/// [`FRAMED_AREA`] stays visible. Zooming and bookmarks are relative to that distance.
+pub fn camera_transform_update(
+    time: Res<Time<Real>>,
+    mut cameras: Query<(&mut OrbitCamera, &mut Transform, &Projection)>,
+) {
+    for (mut camera, mut transform, projection) in cameras.iter_mut() {
+        if let Some(target) = camera.target {
+            let t = 1.0 - (-TRANSITION_RATE * time.delta_seconds()).exp();
+            camera.view = camera.view.lerp(&target, t);
+            if camera.view.focus.distance(target.focus) < 0.001
+                && (camera.view.distance - target.distance).abs() < 0.001
+                && camera.view.yaw_to(&target).abs() < 0.001
+                && (camera.view.pitch - target.pitch).abs() < 0.001
+            {
+                camera.view = target;
+                camera.target = None;
+            }
+        }
+        let framing = match projection {
+            Projection::Perspective(projection) => {
+                let default = CameraView::default();
+                (default.fitting_distance(FRAMED_AREA, projection) / default.distance).max(1.0)
+            }
+            Projection::Orthographic(_) => 1.0,
+        };
+        let view_transform = CameraView {
+            distance: camera.view.distance * framing,
+            ..camera.view
+        }
+        .transform();
+        if *transform != view_transform {
+            *transform = view_transform;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lerp_turns_the_short_way_round() {
+        let target = CameraView::default();
+        let view = CameraView {
+            // Ten turns and a bit away from the target
+            yaw: (target.yaw + 10.0 * TAU + 0.5).rem_euclid(TAU),
+            ..target
+        };
+        assert!((view.yaw_to(&target) + 0.5).abs() < 1e-4);
+        let halfway = view.lerp(&target, 0.5);
+        assert!((halfway.yaw_to(&target) + 0.25).abs() < 1e-4);
+        assert!((0.0..TAU).contains(&halfway.yaw));
+    }
+
+    #[test]
+    fn parsed_yaw_is_wrapped() {
+        let view = CameraView::parse("0 0 0 -1 0 4").unwrap();
+        assert!((view.yaw - (TAU - 1.0)).abs() < 1e-5);
+        assert_eq!(CameraView::parse(&view.to_value()), Some(view));
+    }
+}
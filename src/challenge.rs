@@ -0,0 +1,434 @@
+use bevy::{math::vec3, prelude::*, sprite::Anchor};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    achievements::{AchievementQueue, AchievementStyle},
+    click_analyzer::ClickAnalyzer,
+    localization::Localization,
+    replay::{GameRng, PlayerInput},
+    wall::{HorizontalAlign, WallFont, WallText, TOP_RIGHT_DIGIT_X},
+    ColorTemperature, LightToggled,
+};
+
+const SPRINT_DURATION: f32 = 60.0;
+const RACE_TOGGLES: u32 = 100;
+const PRECISION_TARGETS: u32 = 5;
+/// Largest distance from the target dimmer value that still counts as a hit
+const PRECISION_TOLERANCE: f32 = 0.02;
+/// Seconds the dimmer has to stay on target
+const PRECISION_HOLD: f32 = 0.5;
+/// Smallest distance between consecutive targets
+const PRECISION_MIN_STEP: f32 = 0.2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChallengeMode {
+    /// As many toggles as possible within a minute
+    Sprint,
+    /// 100 toggles as fast as possible
+    RaceTo100,
+    /// Turn the dimmer to the color temperatures shown on the wall
+    PrecisionDimmer,
+}
+
+impl ChallengeMode {
+    pub const ALL: [ChallengeMode; 3] = [
+        ChallengeMode::Sprint,
+        ChallengeMode::RaceTo100,
+        ChallengeMode::PrecisionDimmer,
+    ];
+
+    pub fn name(self, localization: &Localization) -> &str {
+        localization.get(match self {
+            ChallengeMode::Sprint => "challenge.sprint",
+            ChallengeMode::RaceTo100 => "challenge.race_to_100",
+            ChallengeMode::PrecisionDimmer => "challenge.precision_dimmer",
+        })
+    }
+
+    fn key(self) -> KeyCode {
+        match self {
+            ChallengeMode::Sprint => KeyCode::F1,
+            ChallengeMode::RaceTo100 => KeyCode::F2,
+            ChallengeMode::PrecisionDimmer => KeyCode::F3,
+        }
+    }
+}
+
+/// The running challenge. Free play continues alongside it.
+pub struct ActiveChallenge {
+    pub mode: ChallengeMode,
+    /// Seconds since the challenge started
+    pub elapsed: f32,
+    pub toggles: u32,
+    pub targets_hit: u32,
+    /// Dimmer value to hit in [`ChallengeMode::PrecisionDimmer`]
+    pub target: f32,
+    /// Seconds the dimmer has been on target
+    on_target: f32,
+}
+
+/// Outcome of a finished challenge. Formatted when shown, so it follows language changes.
+pub struct ChallengeResult {
+    pub mode: ChallengeMode,
+    pub toggles: u32,
+    /// Seconds the challenge took
+    pub elapsed: f32,
+}
+
+impl ChallengeResult {
+    fn summary(&self, localization: &Localization) -> String {
+        let elapsed = format!("{:.2}", self.elapsed);
+        match self.mode {
+            ChallengeMode::Sprint => localization.format(
+                "challenge.sprint_result",
+                &[("toggles", &self.toggles), ("duration", &SPRINT_DURATION)],
+            ),
+            ChallengeMode::RaceTo100 => localization.format(
+                "challenge.race_result",
+                &[("goal", &RACE_TOGGLES), ("elapsed", &elapsed)],
+            ),
+            ChallengeMode::PrecisionDimmer => localization.format(
+                "challenge.precision_result",
+                &[("goal", &PRECISION_TARGETS), ("elapsed", &elapsed)],
+            ),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Challenge {
+    pub active: Option<ActiveChallenge>,
+    /// Shown until dismissed or another challenge starts
+    pub result: Option<ChallengeResult>,
+}
+
+impl Challenge {
+    fn start(&mut self, mode: ChallengeMode, dimmer: f32, wheel_step: f32, rng: &mut impl Rng) {
+        self.result = None;
+        self.active = Some(ActiveChallenge {
+            mode,
+            elapsed: 0.0,
+            toggles: 0,
+            targets_hit: 0,
+            target: next_target(rng, dimmer, wheel_step),
+            on_target: 0.0,
+        });
+    }
+
+    fn finish(&mut self) {
+        if let Some(active) = self.active.take() {
+            self.result = Some(ChallengeResult {
+                mode: active.mode,
+                toggles: active.toggles,
+                elapsed: active.elapsed,
+            });
+        }
+    }
+}
+
+/// A random dimmer value far enough away from the current one. Targets are a whole number of
+/// mouse wheel steps away, `wheel_step` being the dimmer movement per step at the player's scroll
+/// sensitivity, so they can all be reached with the wheel.
+fn next_target(rng: &mut impl Rng, dimmer: f32, wheel_step: f32) -> f32 {
+    // The small margin keeps rounding errors from ruling out the ends of the range
+    let min_steps = (PRECISION_MIN_STEP / wheel_step - 0.001).ceil() as i32;
+    let steps_down = (dimmer / wheel_step + 0.001).floor() as i32;
+    let steps_up = ((1.0 - dimmer) / wheel_step + 0.001).floor() as i32;
+    let candidates = (-steps_down..=-min_steps)
+        .chain(min_steps..=steps_up)
+        .collect::<Vec<_>>();
+    // Only with huge steps is nothing far enough away
+    let steps = candidates
+        .choose(rng)
+        .copied()
+        .unwrap_or(if steps_up >= steps_down {
+            steps_up
+        } else {
+            -steps_down
+        });
+    (dimmer + steps as f32 * wheel_step).clamp(0.0, 1.0)
+}
+
+/// Marks the HUD line with the challenge timer.
+#[derive(Component)]
+pub struct ChallengeHud;
+
+/// Marks the result screen of the last challenge.
+#[derive(Component)]
+pub struct ChallengeResultPanel;
+
+/// Marks the [`WallText`] showing the target color temperature.
+#[derive(Component)]
+pub struct ChallengeTargetText;
+
+pub fn setup_challenge(
+    mut commands: Commands,
+    wall_font: Res<WallFont>,
+    achievement_style: Res<AchievementStyle>,
+) {
+    commands.init_resource::<Challenge>();
+    commands
+        .spawn(WallText {
+            text: String::new(),
+            font: wall_font.font.clone(),
+            position: IVec2::new(TOP_RIGHT_DIGIT_X as i32, 7),
+            horizontal_align: HorizontalAlign::Right,
+            visible: false,
+            roll: None,
+        })
+        .insert(ChallengeTargetText);
+
+    let text_style = TextStyle {
+        font_size: 14.0,
+        ..achievement_style.text_style.clone()
+    };
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section("", text_style.clone()),
+            text_anchor: Anchor::TopCenter,
+            ..default()
+        })
+        .insert(ChallengeHud);
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section("", text_style),
+            text_anchor: Anchor::Center,
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(ChallengeResultPanel);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn challenge_update(
+    mut challenge: ResMut<Challenge>,
+    player_input: Res<PlayerInput>,
+    mut rng: ResMut<GameRng>,
+    time: Res<Time>,
+    mut toggles: EventReader<LightToggled>,
+    query_temperature: Query<&ColorTemperature>,
+    click_analyzer: Res<ClickAnalyzer>,
+    mut achievement_queue: ResMut<AchievementQueue>,
+) {
+    let dimmer = query_temperature
+        .get_single()
+        .map_or(0.5, |temperature| temperature.value);
+    let wheel_step = ColorTemperature::STEP * player_input.scroll_sensitivity;
+    for mode in ChallengeMode::ALL {
+        if player_input.just_pressed(mode.key()) {
+            challenge.start(mode, dimmer, wheel_step, &mut rng.0);
+        }
+    }
+    if player_input.just_pressed(KeyCode::Back) {
+        challenge.active = None;
+        challenge.result = None;
+    }
+    if player_input.just_pressed(KeyCode::Return) {
+        challenge.result = None;
+    }
+
+    let excluded = click_analyzer.excluded(&player_input);
+    let manual_toggles = toggles.read().filter(|toggle| toggle.manual).count() as u32;
+    let Some(active) = &mut challenge.active else {
+        return;
+    };
+    active.elapsed += time.delta_seconds();
+    if !excluded {
+        active.toggles += manual_toggles;
+    }
+
+    let elapsed = active.elapsed;
+    match active.mode {
+        ChallengeMode::Sprint => {
+            if elapsed < SPRINT_DURATION {
+                return;
+            }
+            let toggles = active.toggles;
+            challenge.finish();
+            achievement_queue.unlock("Sixty seconds of fame");
+            if toggles >= 300 {
+                achievement_queue.unlock("Speed of light");
+            }
+        }
+        ChallengeMode::RaceTo100 => {
+            if active.toggles < RACE_TOGGLES {
+                return;
+            }
+            challenge.finish();
+            achievement_queue.unlock("Photo finish");
+            if elapsed < 20.0 {
+                achievement_queue.unlock("Flash");
+            }
+        }
+        ChallengeMode::PrecisionDimmer => {
+            if (dimmer - active.target).abs() <= PRECISION_TOLERANCE {
+                active.on_target += time.delta_seconds();
+            } else {
+                active.on_target = 0.0;
+            }
+            if active.on_target < PRECISION_HOLD {
+                return;
+            }
+            active.targets_hit += 1;
+            active.on_target = 0.0;
+            active.target = next_target(&mut rng.0, dimmer, wheel_step);
+            if active.targets_hit < PRECISION_TARGETS {
+                return;
+            }
+            challenge.finish();
+            achievement_queue.unlock("Color calibrated");
+            if elapsed < 20.0 {
+                achievement_queue.unlock("Steady hand");
+            }
+        }
+    }
+}
+
+pub fn challenge_display_update(
+    challenge: Res<Challenge>,
+    localization: Res<Localization>,
+    query_ortho: Query<&OrthographicProjection>,
+    mut hud: Query<(&mut Text, &mut Transform), With<ChallengeHud>>,
+    mut target_text: Query<&mut WallText, With<ChallengeTargetText>>,
+) {
+    let Ok(ortho) = query_ortho.get_single() else {
+        return;
+    };
+
+    let target = challenge
+        .active
+        .as_ref()
+        .filter(|active| active.mode == ChallengeMode::PrecisionDimmer)
+        .map(|active| format!("{:.0}K", ColorTemperature::kelvin(active.target)));
+    for mut text in target_text.iter_mut() {
+        // Only touch the text when it changes, every change redraws the wall text
+        if text.visible != target.is_some() {
+            text.visible = target.is_some();
+        }
+        if let Some(target) = &target {
+            if &text.text != target {
+                text.text = target.clone();
+            }
+        }
+    }
+
+    for (mut text, mut transform) in hud.iter_mut() {
+        transform.translation = vec3(ortho.area.center().x, ortho.area.max.y - 10.0, 0.0);
+        text.sections[0].value = match &challenge.active {
+            None => localization.get("challenge.menu").to_string(),
+            Some(active) => {
+                let elapsed = format!("{:.1}", active.elapsed);
+                let progress = match active.mode {
+                    ChallengeMode::Sprint => localization.format(
+                        "challenge.sprint_progress",
+                        &[
+                            (
+                                "left",
+                                &format!("{:.1}", (SPRINT_DURATION - active.elapsed).max(0.0)),
+                            ),
+                            ("toggles", &active.toggles),
+                        ],
+                    ),
+                    ChallengeMode::RaceTo100 => localization.format(
+                        "challenge.race_progress",
+                        &[
+                            ("elapsed", &elapsed),
+                            ("toggles", &active.toggles),
+                            ("goal", &RACE_TOGGLES),
+                        ],
+                    ),
+                    ChallengeMode::PrecisionDimmer => localization.format(
+                        "challenge.precision_progress",
+                        &[
+                            ("elapsed", &elapsed),
+                            ("hit", &active.targets_hit),
+                            ("goal", &PRECISION_TARGETS),
+                        ],
+                    ),
+                };
+                localization.format(
+                    "challenge.hud",
+                    &[
+                        ("mode", &active.mode.name(&localization)),
+                        ("progress", &progress),
+                    ],
+                )
+            }
+        };
+    }
+}
+
+pub fn challenge_result_update(
+    challenge: Res<Challenge>,
+    localization: Res<Localization>,
+    query_ortho: Query<&OrthographicProjection>,
+    mut result_panel: Query<
+        (&mut Text, &mut Transform, &mut Visibility),
+        With<ChallengeResultPanel>,
+    >,
+) {
+    let Ok(ortho) = query_ortho.get_single() else {
+        return;
+    };
+    for (mut text, mut transform, mut visibility) in result_panel.iter_mut() {
+        transform.translation = vec3(ortho.area.center().x, ortho.area.center().y, 2.0);
+        let Some(result) = &challenge.result else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        text.sections[0].value = localization.format(
+            "challenge.result",
+            &[
+                ("mode", &result.mode.name(&localization).to_uppercase()),
+                ("summary", &result.summary(&localization)),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Scrolls the dimmer towards the target in whole wheel steps, the way
+    /// `light_temperature_update` moves it.
+    fn scroll_to(dimmer: f32, target: f32, scroll_sensitivity: f32) -> f32 {
+        let wheel_step = ColorTemperature::STEP * scroll_sensitivity;
+        let steps = ((target - dimmer) / wheel_step).round() as i32;
+        (0..steps.abs()).fold(dimmer, |dimmer, _| {
+            (dimmer + steps.signum() as f32 * scroll_sensitivity * ColorTemperature::STEP)
+                .clamp(0.0, 1.0)
+        })
+    }
+
+    #[test]
+    fn every_target_is_reachable_with_the_wheel() {
+        for scroll_sensitivity in [0.25, 0.75, 1.0, 1.25, 1.5, 2.75, 4.0] {
+            let wheel_step = ColorTemperature::STEP * scroll_sensitivity;
+            let mut rng = StdRng::seed_from_u64(0);
+            let mut dimmer = 0.5;
+            for _ in 0..1000 {
+                let target = next_target(&mut rng, dimmer, wheel_step);
+                assert!((0.0..=1.0).contains(&target));
+                assert!((target - dimmer).abs() >= PRECISION_MIN_STEP - 0.001);
+                dimmer = scroll_to(dimmer, target, scroll_sensitivity);
+                assert!(
+                    (dimmer - target).abs() <= PRECISION_TOLERANCE,
+                    "target {target} is out of reach at {scroll_sensitivity}x"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn targets_cover_both_directions() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let targets = (0..100)
+            .map(|_| next_target(&mut rng, 0.5, ColorTemperature::STEP * 1.5))
+            .collect::<Vec<_>>();
+        assert!(targets.iter().any(|&target| target < 0.5));
+        assert!(targets.iter().any(|&target| target > 0.5));
+    }
+}
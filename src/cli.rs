@@ -0,0 +1,250 @@
+use bevy::prelude::*;
+use std::{fmt, path::PathBuf};
+
+use crate::wall::{WallRenderer, PALETTES};
+
+pub const USAGE: &str = "\
+Usage: aca_gamejam_winner2023 [OPTIONS]
+
+Options:
+  --seed <N>               Seed for the wall pattern
+  --window-size <W>x<H>    Window size in logical pixels
+  --fullscreen             Start in borderless fullscreen
+  --score <N>              Score to start the session with
+  --palette <NAME>         Wall palette: night, sunset, forest, mono, high_contrast,
+                           deuteranopia, protanopia or tritanopia
+  --mute                   Start without sound
+  --replay <FILE>          Play back a recorded session
+  --clock <TIME>           Pretend the wall clock shows this time (HH:MM or RFC 3339)
+  --headless               Run a replay without window and rendering (needs --replay)
+  --wall-renderer <NAME>   Draw the wall as tiles (one entity per tile) or as a single mesh
+  --wall-benchmark         Compare the frame times of both wall renderers with vsync off, then
+                           exit
+  -h, --help               Show this help";
+
+/// Command line options, parsed before the app is built.
+#[derive(Resource, Default, Debug)]
+pub struct Options {
+    pub seed: Option<u64>,
+    pub window_size: Option<Vec2>,
+    pub fullscreen: bool,
+    pub score: Option<u64>,
+    pub palette: Option<String>,
+    pub mute: bool,
+    pub replay: Option<PathBuf>,
+    pub clock: Option<chrono::DateTime<chrono::Local>>,
+    pub headless: bool,
+    pub wall_renderer: WallRenderer,
+    pub wall_benchmark: bool,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    /// `--help` was given
+    Help,
+    UnknownOption(String),
+    MissingValue(&'static str),
+    InvalidValue {
+        option: &'static str,
+        value: String,
+    },
+    /// `option` only makes sense together with `needs`
+    Requires {
+        option: &'static str,
+        needs: &'static str,
+    },
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Help => write!(f, "help requested"),
+            CliError::UnknownOption(option) => write!(f, "unknown option `{option}`"),
+            CliError::MissingValue(option) => write!(f, "`{option}` needs a value"),
+            CliError::InvalidValue { option, value } => {
+                write!(f, "invalid value `{value}` for `{option}`")
+            }
+            CliError::Requires { option, needs } => {
+                write!(f, "`{option}` needs `{needs}`")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, CliError> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Both `--option value` and `--option=value` are accepted
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => {
+                    (name.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+            let mut value = |option: &'static str| {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or(CliError::MissingValue(option))
+            };
+            match name.as_str() {
+                "-h" | "--help" => return Err(CliError::Help),
+                "--seed" => options.seed = Some(parse("--seed", value("--seed")?)?),
+                "--window-size" => {
+                    let size = value("--window-size")?;
+                    options.window_size =
+                        Some(parse_size(&size).ok_or(CliError::InvalidValue {
+                            option: "--window-size",
+                            value: size,
+                        })?);
+                }
+                "--fullscreen" => options.fullscreen = true,
+                "--score" => options.score = Some(parse("--score", value("--score")?)?),
+                "--palette" => {
+                    let palette = value("--palette")?;
+                    if !PALETTES.iter().any(|(name, _, _)| *name == palette) {
+                        return Err(CliError::InvalidValue {
+                            option: "--palette",
+                            value: palette,
+                        });
+                    }
+                    options.palette = Some(palette);
+                }
+                "--mute" => options.mute = true,
+                "--replay" => options.replay = Some(value("--replay")?.into()),
+                "--clock" => {
+                    let clock = value("--clock")?;
+                    options.clock = Some(parse_clock(&clock).ok_or(CliError::InvalidValue {
+                        option: "--clock",
+                        value: clock,
+                    })?);
+                }
+                "--headless" => options.headless = true,
+                "--wall-renderer" => {
+                    let renderer = value("--wall-renderer")?;
+                    options.wall_renderer =
+                        WallRenderer::named(&renderer).ok_or(CliError::InvalidValue {
+                            option: "--wall-renderer",
+                            value: renderer,
+                        })?;
+                }
+                "--wall-benchmark" => options.wall_benchmark = true,
+                _ => return Err(CliError::UnknownOption(name)),
+            }
+        }
+        // Without a replay to play back, nothing would ever leave the menu
+        if options.headless && options.replay.is_none() {
+            return Err(CliError::Requires {
+                option: "--headless",
+                needs: "--replay",
+            });
+        }
+        Ok(options)
+    }
+}
+
+fn parse<T: std::str::FromStr>(option: &'static str, value: String) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError::InvalidValue { option, value })
+}
+
+/// `1280x720`
+fn parse_size(size: &str) -> Option<Vec2> {
+    let (width, height) = size.split_once('x')?;
+    let size = Vec2::new(width.parse().ok()?, height.parse().ok()?);
+    (size.x > 0.0 && size.y > 0.0).then_some(size)
+}
+
+/// `23:59` for today, or a full RFC 3339 timestamp.
+fn parse_clock(clock: &str) -> Option<chrono::DateTime<chrono::Local>> {
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(clock) {
+        return Some(time.with_timezone(&chrono::Local));
+    }
+    let time = chrono::NaiveTime::parse_from_str(clock, "%H:%M").ok()?;
+    chrono::Local::now()
+        .date_naive()
+        .and_time(time)
+        .and_local_timezone(chrono::Local)
+        .earliest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Options, CliError> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_values_in_both_forms() {
+        let options = parse_args(&[
+            "--seed",
+            "7",
+            "--window-size=1280x720",
+            "--palette",
+            "forest",
+            "--clock=2023-11-25T14:03:00+01:00",
+            "--wall-renderer",
+            "mesh",
+            "--mute",
+        ])
+        .unwrap();
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.window_size, Some(Vec2::new(1280.0, 720.0)));
+        assert_eq!(options.palette.as_deref(), Some("forest"));
+        assert_eq!(
+            options.clock.map(|clock| clock.timestamp()),
+            Some(1_700_917_380)
+        );
+        assert_eq!(options.wall_renderer, WallRenderer::Mesh);
+        assert!(options.mute && !options.fullscreen);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(matches!(parse_args(&["--help"]), Err(CliError::Help)));
+        assert!(matches!(
+            parse_args(&["--loud"]),
+            Err(CliError::UnknownOption(option)) if option == "--loud"
+        ));
+        assert!(matches!(
+            parse_args(&["--seed"]),
+            Err(CliError::MissingValue("--seed"))
+        ));
+        assert!(matches!(
+            parse_args(&["--window-size", "0x720"]),
+            Err(CliError::InvalidValue {
+                option: "--window-size",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_args(&["--palette=neon"]),
+            Err(CliError::InvalidValue {
+                option: "--palette",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_args(&["--clock", "25:00"]),
+            Err(CliError::InvalidValue {
+                option: "--clock",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_args(&["--headless"]),
+            Err(CliError::Requires {
+                option: "--headless",
+                needs: "--replay"
+            })
+        ));
+        assert!(parse_args(&["--headless", "--replay", "session.replay"]).is_ok());
+    }
+}
@@ -0,0 +1,170 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::{achievements::AchievementQueue, replay::PlayerInput};
+
+#[derive(Resource)]
+pub struct ClickAnalyzerSettings {
+    /// Number of recent clicks that are analyzed
+    pub window: usize,
+    /// Faster sustained clicking (clicks per second) is considered impossible for a human
+    pub max_rate: f64,
+    /// Intervals with a smaller coefficient of variation are too regular for a human
+    pub min_variation: f64,
+    /// Intervals this close to each other (seconds) count as identical
+    pub period_tolerance: f64,
+    /// Share of identical intervals above which the clicks count as perfectly periodic
+    pub max_periodic_share: f64,
+}
+
+impl Default for ClickAnalyzerSettings {
+    fn default() -> Self {
+        Self {
+            window: 32,
+            max_rate: 25.0,
+            min_variation: 0.03,
+            period_tolerance: 0.001,
+            max_periodic_share: 0.9,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Suspicion {
+    ImpossibleRate,
+    TooRegular,
+    Periodic,
+}
+
+/// Watches the timing of manual toggles for signs of an autoclicker.
+/// Once flagged, the session stays suspicious.
+#[derive(Resource, Default)]
+pub struct ClickAnalyzer {
+    timestamps: VecDeque<f64>,
+    pub suspicion: Option<Suspicion>,
+}
+
+impl ClickAnalyzer {
+    pub fn is_suspicious(&self) -> bool {
+        self.suspicion.is_some()
+    }
+
+    /// Whether clicks should currently be left out of the score and statistics.
+    pub fn excluded(&self, input: &PlayerInput) -> bool {
+        input.exclude_suspicious_clicks && self.is_suspicious()
+    }
+
+    fn analyze(&self, settings: &ClickAnalyzerSettings) -> Option<Suspicion> {
+        if self.timestamps.len() < settings.window {
+            return None;
+        }
+        let intervals = self
+            .timestamps
+            .iter()
+            .zip(self.timestamps.iter().skip(1))
+            .map(|(a, b)| b - a)
+            .collect::<Vec<_>>();
+        let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+        if mean <= 0.0 || 1.0 / mean > settings.max_rate {
+            return Some(Suspicion::ImpossibleRate);
+        }
+
+        let variance =
+            intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / intervals.len() as f64;
+        if variance.sqrt() / mean < settings.min_variation {
+            return Some(Suspicion::TooRegular);
+        }
+
+        let identical = intervals
+            .iter()
+            .zip(intervals.iter().skip(1))
+            .filter(|(a, b)| (*a - *b).abs() <= settings.period_tolerance)
+            .count();
+        if identical as f64 / (intervals.len() - 1) as f64 > settings.max_periodic_share {
+            return Some(Suspicion::Periodic);
+        }
+        None
+    }
+}
+
+pub fn setup_click_analyzer(mut commands: Commands) {
+    commands.init_resource::<ClickAnalyzer>();
+    commands.init_resource::<ClickAnalyzerSettings>();
+}
+
+pub fn click_analyzer_update(
+    mut analyzer: ResMut<ClickAnalyzer>,
+    settings: Res<ClickAnalyzerSettings>,
+    player_input: Res<PlayerInput>,
+    time: Res<Time>,
+    mut achievement_queue: ResMut<AchievementQueue>,
+) {
+    let releases = player_input.switch_releases();
+    if releases == 0 {
+        return;
+    }
+    for _ in 0..releases {
+        analyzer.timestamps.push_back(time.elapsed_seconds_f64());
+    }
+    while analyzer.timestamps.len() > settings.window {
+        analyzer.timestamps.pop_front();
+    }
+
+    if analyzer.is_suspicious() {
+        return;
+    }
+    if let Some(suspicion) = analyzer.analyze(&settings) {
+        warn!("Suspicious clicking detected: {suspicion:?}");
+        analyzer.suspicion = Some(suspicion);
+        achievement_queue.unlock("Robot");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An analyzer that saw clicks with these intervals.
+    fn clicked(intervals: impl IntoIterator<Item = f64>) -> ClickAnalyzer {
+        let mut analyzer = ClickAnalyzer::default();
+        let mut time = 0.0;
+        analyzer.timestamps.push_back(time);
+        for interval in intervals {
+            time += interval;
+            analyzer.timestamps.push_back(time);
+        }
+        analyzer
+    }
+
+    #[test]
+    fn detects_autoclickers() {
+        let settings = ClickAnalyzerSettings::default();
+        let clicks = settings.window - 1;
+        assert_eq!(
+            clicked(vec![0.02; clicks]).analyze(&settings),
+            Some(Suspicion::ImpossibleRate)
+        );
+        assert_eq!(
+            clicked(vec![0.1; clicks]).analyze(&settings),
+            Some(Suspicion::TooRegular)
+        );
+        let mut intervals = vec![0.1; clicks];
+        intervals[clicks / 2] = 0.5;
+        assert_eq!(
+            clicked(intervals).analyze(&settings),
+            Some(Suspicion::Periodic)
+        );
+    }
+
+    #[test]
+    fn humans_are_not_suspicious() {
+        let settings = ClickAnalyzerSettings::default();
+        let human = (0..settings.window - 1).map(|i| 0.1 + 0.02 * ((i * 7) % 5) as f64);
+        assert_eq!(clicked(human).analyze(&settings), None);
+        // Too few clicks to tell
+        assert_eq!(
+            clicked(vec![0.02; settings.window - 2]).analyze(&settings),
+            None
+        );
+    }
+}
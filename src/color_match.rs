@@ -0,0 +1,387 @@
+use bevy::{math::vec3, prelude::*, sprite::Anchor};
+use rand::Rng;
+
+use crate::{
+    achievements::{AchievementQueue, AchievementStyle},
+    color_temperature_to_rgb,
+    localization::Localization,
+    replay::{GameRng, PlayerInput},
+    ColorTemperature,
+};
+
+const PATCH_SIZE: f32 = 0.8;
+/// Color distances below this are indistinguishable
+const PERFECT_DELTA_E: f32 = 1.0;
+/// Every unit of color distance costs this many points
+const POINTS_PER_DELTA_E: f32 = 5.0;
+const PERFECT_STREAK: u32 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ColorMatchDifficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl ColorMatchDifficulty {
+    pub fn name(self, localization: &Localization) -> &str {
+        localization.get(match self {
+            ColorMatchDifficulty::Easy => "color_match.easy",
+            ColorMatchDifficulty::Normal => "color_match.normal",
+            ColorMatchDifficulty::Hard => "color_match.hard",
+        })
+    }
+
+    fn next(self) -> Self {
+        match self {
+            ColorMatchDifficulty::Easy => ColorMatchDifficulty::Normal,
+            ColorMatchDifficulty::Normal => ColorMatchDifficulty::Hard,
+            ColorMatchDifficulty::Hard => ColorMatchDifficulty::Easy,
+        }
+    }
+
+    /// Seconds to lock in a match
+    fn time_limit(self) -> f32 {
+        match self {
+            ColorMatchDifficulty::Easy => 15.0,
+            ColorMatchDifficulty::Normal => 10.0,
+            ColorMatchDifficulty::Hard => 5.0,
+        }
+    }
+
+    /// Harder rounds use the cooler end of the range, where the colors are closer together
+    fn dimmer_range(self) -> (f32, f32) {
+        match self {
+            ColorMatchDifficulty::Easy => (0.0, 0.5),
+            ColorMatchDifficulty::Normal => (0.0, 1.0),
+            ColorMatchDifficulty::Hard => (0.5, 1.0),
+        }
+    }
+}
+
+pub struct ColorMatchRound {
+    /// Dimmer value producing the target color
+    pub target: f32,
+    pub time_left: f32,
+}
+
+pub struct ColorMatchResult {
+    pub delta_e: f32,
+    pub points: u32,
+}
+
+/// Match the color patch on the wall with the dimmer.
+#[derive(Resource, Default)]
+pub struct ColorMatch {
+    pub difficulty: ColorMatchDifficulty,
+    pub round: Option<ColorMatchRound>,
+    pub result: Option<ColorMatchResult>,
+    pub best_points: u32,
+    /// Perfect matches in a row
+    pub perfect_streak: u32,
+}
+
+/// The patches showing the target color and the current dimmer color.
+#[derive(Resource)]
+pub struct ColorMatchPatches {
+    target: Handle<StandardMaterial>,
+    current: Handle<StandardMaterial>,
+}
+
+#[derive(Component)]
+pub struct ColorMatchPatch;
+
+#[derive(Component)]
+pub struct ColorMatchHud;
+
+fn dimmer_color(value: f32) -> Color {
+    color_temperature_to_rgb(ColorTemperature::kelvin(value))
+        .extend(1.0)
+        .into()
+}
+
+/// CIELAB coordinates of an sRGB color, relative to the D65 white point.
+fn lab(color: Color) -> Vec3 {
+    let [r, g, b, _] = color.as_linear_rgba_f32();
+    let xyz = Mat3::from_cols(
+        vec3(0.4124564, 0.2126729, 0.0193339),
+        vec3(0.3575761, 0.7151522, 0.119192),
+        vec3(0.1804375, 0.0721750, 0.9503041),
+    ) * vec3(r, g, b)
+        / vec3(0.95047, 1.0, 1.08883);
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(xyz.x), f(xyz.y), f(xyz.z));
+    vec3(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// Perceptual color distance following the CIEDE2000 formula.
+fn ciede2000(lab1: Vec3, lab2: Vec3) -> f32 {
+    let (l1, a1, b1) = (lab1.x, lab1.y, lab1.z);
+    let (l2, a2, b2) = (lab2.x, lab2.y, lab2.z);
+
+    let c_mean = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (c_mean.powi(7) / (c_mean.powi(7) + 25f32.powi(7))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |a: f32, b: f32| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 <= h1 {
+        h2 - h1 + 360.0
+    } else {
+        h2 - h1 - 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_mean - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_mean).to_radians().cos()
+        + 0.32 * (3.0 * h_mean + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_mean - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (c_mean.powi(7) / (c_mean.powi(7) + 25f32.powi(7))).sqrt();
+    let s_l = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean;
+    let s_h = 1.0 + 0.015 * c_mean * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    ((delta_l / s_l).powi(2)
+        + (delta_c / s_c).powi(2)
+        + (delta_h / s_h).powi(2)
+        + r_t * (delta_c / s_c) * (delta_h / s_h))
+        .sqrt()
+}
+
+pub fn setup_color_match(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    achievement_style: Res<AchievementStyle>,
+) {
+    commands.init_resource::<ColorMatch>();
+
+    let mesh = meshes.add(shape::Quad::new(Vec2::splat(PATCH_SIZE)).into());
+    let mut patch_material = || {
+        materials.add(StandardMaterial {
+            // Unaffected by the light, so the colors can be compared
+            unlit: true,
+            ..Color::BLACK.into()
+        })
+    };
+    let patches = ColorMatchPatches {
+        target: patch_material(),
+        current: patch_material(),
+    };
+    for (material, x) in [(&patches.target, -3.6), (&patches.current, -2.7)] {
+        commands
+            .spawn(PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_xyz(x, -0.8, 0.01),
+                visibility: Visibility::Hidden,
+                ..default()
+            })
+            .insert(ColorMatchPatch);
+    }
+    commands.insert_resource(patches);
+
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 14.0,
+                    ..achievement_style.text_style.clone()
+                },
+            ),
+            text_anchor: Anchor::BottomCenter,
+            ..default()
+        })
+        .insert(ColorMatchHud);
+}
+
+pub fn color_match_update(
+    mut color_match: ResMut<ColorMatch>,
+    player_input: Res<PlayerInput>,
+    mut rng: ResMut<GameRng>,
+    time: Res<Time>,
+    query_temperature: Query<&ColorTemperature>,
+    mut achievement_queue: ResMut<AchievementQueue>,
+) {
+    let Ok(temperature) = query_temperature.get_single() else {
+        return;
+    };
+
+    let Some(round) = &mut color_match.round else {
+        if player_input.just_pressed(KeyCode::F5) {
+            color_match.difficulty = color_match.difficulty.next();
+        }
+        if player_input.just_pressed(KeyCode::F4) {
+            let difficulty = color_match.difficulty;
+            let (min, max) = difficulty.dimmer_range();
+            color_match.result = None;
+            color_match.round = Some(ColorMatchRound {
+                target: rng.0.gen_range(min..=max),
+                time_left: difficulty.time_limit(),
+            });
+        }
+        return;
+    };
+
+    round.time_left -= time.delta_seconds();
+    if !player_input.just_pressed(KeyCode::Space) && round.time_left > 0.0 {
+        return;
+    }
+    let target = round.target;
+    color_match.round = None;
+
+    let delta_e = ciede2000(
+        lab(dimmer_color(target)),
+        lab(dimmer_color(temperature.value)),
+    );
+    let points = (100.0 - delta_e * POINTS_PER_DELTA_E).max(0.0).round() as u32;
+    color_match.best_points = color_match.best_points.max(points);
+    color_match.result = Some(ColorMatchResult { delta_e, points });
+
+    if delta_e >= PERFECT_DELTA_E {
+        color_match.perfect_streak = 0;
+        return;
+    }
+    color_match.perfect_streak += 1;
+    achievement_queue.unlock("Perfect match");
+    if color_match.difficulty == ColorMatchDifficulty::Hard {
+        achievement_queue.unlock("Hawk eye");
+    }
+    if color_match.perfect_streak >= PERFECT_STREAK {
+        achievement_queue.unlock("Color sommelier");
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn color_match_display_update(
+    color_match: Res<ColorMatch>,
+    patches: Res<ColorMatchPatches>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query_temperature: Query<&ColorTemperature>,
+    localization: Res<Localization>,
+    query_ortho: Query<&OrthographicProjection>,
+    mut query_patches: Query<&mut Visibility, With<ColorMatchPatch>>,
+    mut hud: Query<(&mut Text, &mut Transform), With<ColorMatchHud>>,
+) {
+    let visibility = if color_match.round.is_some() {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for mut patch_visibility in query_patches.iter_mut() {
+        *patch_visibility = visibility;
+    }
+    if let Some(round) = &color_match.round {
+        if let Some(material) = materials.get_mut(&patches.target) {
+            material.base_color = dimmer_color(round.target);
+        }
+        if let Ok(temperature) = query_temperature.get_single() {
+            if let Some(material) = materials.get_mut(&patches.current) {
+                material.base_color = dimmer_color(temperature.value);
+            }
+        }
+    }
+
+    let Ok(ortho) = query_ortho.get_single() else {
+        return;
+    };
+    for (mut text, mut transform) in hud.iter_mut() {
+        transform.translation = vec3(ortho.area.center().x, ortho.area.min.y + 10.0, 0.0);
+        let difficulty = color_match.difficulty.name(&localization);
+        text.sections[0].value = match (&color_match.round, &color_match.result) {
+            (Some(round), _) => localization.format(
+                "color_match.round",
+                &[("time", &format!("{:.1}", round.time_left.max(0.0)))],
+            ),
+            (None, Some(result)) => localization.format(
+                "color_match.result",
+                &[
+                    ("delta_e", &format!("{:.2}", result.delta_e)),
+                    ("points", &result.points),
+                    ("best", &color_match.best_points),
+                    ("difficulty", &difficulty),
+                ],
+            ),
+            (None, None) => localization.format("color_match.idle", &[("difficulty", &difficulty)]),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pairs and distances from Sharma, Wu and Dalal, "The CIEDE2000 Color-Difference Formula"
+    const REFERENCE_PAIRS: [(Vec3, Vec3, f32); 5] = [
+        (
+            vec3(50.0, 2.6772, -79.7751),
+            vec3(50.0, 0.0, -82.7485),
+            2.0425,
+        ),
+        (vec3(50.0, 0.0, 0.0), vec3(50.0, -1.0, 2.0), 2.3669),
+        (vec3(50.0, 2.5, 0.0), vec3(50.0, 0.0, -2.5), 4.3065),
+        (vec3(50.0, 2.5, 0.0), vec3(73.0, 25.0, -18.0), 27.1492),
+        (
+            vec3(2.0776, 0.0795, -1.135),
+            vec3(0.9033, -0.0636, -0.5514),
+            0.9082,
+        ),
+    ];
+
+    #[test]
+    fn ciede2000_matches_reference_data() {
+        for (lab1, lab2, expected) in REFERENCE_PAIRS {
+            for delta_e in [ciede2000(lab1, lab2), ciede2000(lab2, lab1)] {
+                assert!(
+                    (delta_e - expected).abs() < 1e-3,
+                    "{lab1} {lab2}: {delta_e} instead of {expected}"
+                );
+            }
+        }
+        assert_eq!(
+            ciede2000(vec3(50.0, 10.0, -10.0), vec3(50.0, 10.0, -10.0)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn lab_of_white_and_black() {
+        assert!(lab(Color::WHITE).distance(vec3(100.0, 0.0, 0.0)) < 0.01);
+        assert!(lab(Color::BLACK).length() < 0.01);
+    }
+}
@@ -0,0 +1,297 @@
+use bevy::{math::vec3, prelude::*, sprite::Anchor};
+use std::collections::VecDeque;
+
+use crate::{
+    achievements::{AchievementQueue, AchievementStyle},
+    localization::Localization,
+    LightToggled,
+};
+
+const COMBO_METER_WIDTH: f32 = 200.0;
+const COMBO_METER_HEIGHT: f32 = 8.0;
+/// Number of recent intervals used to judge the rhythm
+const RHYTHM_WINDOW: usize = 8;
+
+/// Times are in seconds.
+#[derive(Resource)]
+pub struct ComboSettings {
+    /// The combo breaks when the player stops toggling for this long
+    pub idle_time: f32,
+    /// Toggles closer together than this keep the combo going
+    pub fast_interval: f32,
+    /// Largest relative deviation of the recent intervals that still counts as a rhythm
+    pub rhythm_tolerance: f32,
+    /// Toggles needed for every additional multiplier step
+    pub toggles_per_step: u32,
+    pub max_multiplier: u32,
+}
+
+impl Default for ComboSettings {
+    fn default() -> Self {
+        Self {
+            idle_time: 1.5,
+            fast_interval: 0.35,
+            rhythm_tolerance: 0.1,
+            toggles_per_step: 10,
+            max_multiplier: 8,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Combo {
+    pub count: u32,
+    pub best: u32,
+    /// Whether the recent toggles kept a steady rhythm
+    pub in_rhythm: bool,
+    last_toggle: Option<f64>,
+    intervals: VecDeque<f32>,
+}
+
+impl Combo {
+    /// Applies to the score and the lumens earned by manual toggles.
+    pub fn multiplier(&self, settings: &ComboSettings) -> u32 {
+        let steps = 1 + self.count / settings.toggles_per_step.max(1);
+        let rhythm_bonus = self.in_rhythm as u32;
+        (steps + rhythm_bonus).min(settings.max_multiplier)
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+        self.in_rhythm = false;
+        self.intervals.clear();
+    }
+
+    fn rhythm(&self, tolerance: f32) -> bool {
+        if self.intervals.len() < RHYTHM_WINDOW {
+            return false;
+        }
+        let mean = self.intervals.iter().sum::<f32>() / self.intervals.len() as f32;
+        let variance = self
+            .intervals
+            .iter()
+            .map(|interval| (interval - mean).powi(2))
+            .sum::<f32>()
+            / self.intervals.len() as f32;
+        variance.sqrt() <= tolerance * mean
+    }
+}
+
+#[derive(Component)]
+pub struct ComboMeter;
+
+#[derive(Component)]
+pub struct ComboMeterBar;
+
+pub fn setup_combo(mut commands: Commands, achievement_style: Res<AchievementStyle>) {
+    commands.init_resource::<Combo>();
+    commands.init_resource::<ComboSettings>();
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section("", achievement_style.text_style.clone()),
+            text_anchor: Anchor::BottomLeft,
+            ..default()
+        })
+        .insert(ComboMeter)
+        .with_children(|builder| {
+            builder
+                .spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::hex("#FFC436").unwrap(),
+                        custom_size: Some(Vec2::new(COMBO_METER_WIDTH, COMBO_METER_HEIGHT)),
+                        anchor: Anchor::TopLeft,
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, -4.0, 0.0),
+                    ..default()
+                })
+                .insert(ComboMeterBar);
+        });
+}
+
+pub fn combo_update(
+    mut combo: ResMut<Combo>,
+    settings: Res<ComboSettings>,
+    time: Res<Time>,
+    mut toggles: EventReader<LightToggled>,
+    mut achievement_queue: ResMut<AchievementQueue>,
+) {
+    let now = time.elapsed_seconds_f64();
+    for _ in toggles.read().filter(|toggle| toggle.manual) {
+        let interval = combo.last_toggle.map(|last| (now - last) as f32);
+        combo.last_toggle = Some(now);
+        let Some(interval) = interval.filter(|&interval| interval < settings.idle_time) else {
+            combo.reset();
+            combo.count = 1;
+            continue;
+        };
+
+        combo.intervals.push_back(interval);
+        if combo.intervals.len() > RHYTHM_WINDOW {
+            combo.intervals.pop_front();
+        }
+        combo.in_rhythm = combo.rhythm(settings.rhythm_tolerance);
+        if interval <= settings.fast_interval || combo.in_rhythm {
+            combo.count += 1;
+        } else {
+            combo.reset();
+            combo.count = 1;
+        }
+        combo.best = combo.best.max(combo.count);
+
+        if combo.in_rhythm {
+            achievement_queue.unlock("In the groove");
+        }
+        for (count, text) in [
+            (10, "Combo breaker? Never heard of it"),
+            (50, "Flickering frenzy"),
+            (100, "Strobe light"),
+        ] {
+            if combo.count >= count {
+                achievement_queue.unlock(text);
+            }
+        }
+    }
+
+    let idle =
+        !matches!(combo.last_toggle, Some(last) if ((now - last) as f32) < settings.idle_time);
+    if idle && combo.count > 0 {
+        combo.reset();
+    }
+}
+
+pub fn combo_meter_update(
+    combo: Res<Combo>,
+    settings: Res<ComboSettings>,
+    time: Res<Time>,
+    localization: Res<Localization>,
+    query_ortho: Query<&OrthographicProjection>,
+    mut meter: Query<(&mut Text, &mut Transform, &mut Visibility), With<ComboMeter>>,
+    mut bar: Query<&mut Transform, (With<ComboMeterBar>, Without<ComboMeter>)>,
+) {
+    let Ok(ortho) = query_ortho.get_single() else {
+        return;
+    };
+    let Ok((mut text, mut transform, mut visibility)) = meter.get_single_mut() else {
+        return;
+    };
+    transform.translation = vec3(ortho.area.min.x + 10.0, ortho.area.min.y + 20.0, 0.0);
+    *visibility = if combo.count > 1 {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+
+    if combo.is_changed() || localization.is_changed() {
+        let mut meter = localization.format(
+            "combo.meter",
+            &[
+                ("count", &combo.count),
+                ("multiplier", &combo.multiplier(&settings)),
+            ],
+        );
+        if combo.in_rhythm {
+            meter = format!("{meter} {}", localization.get("combo.rhythm"));
+        }
+        text.sections[0].value = meter;
+    }
+
+    // The bar shrinks until the combo breaks
+    let remaining = combo.last_toggle.map_or(0.0, |last| {
+        1.0 - ((time.elapsed_seconds_f64() - last) as f32 / settings.idle_time).clamp(0.0, 1.0)
+    });
+    for mut bar in bar.iter_mut() {
+        bar.scale.x = remaining;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    #[test]
+    fn multiplier_grows_every_ten_toggles_up_to_the_cap() {
+        let settings = ComboSettings::default();
+        let multiplier = |count, in_rhythm| {
+            Combo {
+                count,
+                in_rhythm,
+                ..default()
+            }
+            .multiplier(&settings)
+        };
+        assert_eq!(multiplier(0, false), 1);
+        assert_eq!(multiplier(9, false), 1);
+        assert_eq!(multiplier(10, false), 2);
+        assert_eq!(multiplier(25, false), 3);
+        assert_eq!(multiplier(25, true), 4);
+        assert_eq!(multiplier(69, true), 8);
+        assert_eq!(multiplier(70, false), 8);
+        assert_eq!(multiplier(1000, true), 8);
+    }
+
+    /// Runs [`combo_update`] on its own, with the game time advancing by 100 ms per update.
+    fn combo_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .init_resource::<Combo>()
+            .init_resource::<ComboSettings>()
+            .init_resource::<AchievementQueue>()
+            .add_event::<LightToggled>()
+            .add_systems(Update, combo_update);
+        app.update();
+        app
+    }
+
+    fn toggle(app: &mut App, manual: bool) {
+        app.world.send_event(LightToggled { manual });
+        app.update();
+    }
+
+    fn idle(app: &mut App, updates: usize) {
+        for _ in 0..updates {
+            app.update();
+        }
+    }
+
+    #[test]
+    fn fast_manual_toggles_build_the_combo() {
+        let mut app = combo_app();
+        for _ in 0..5 {
+            toggle(&mut app, true);
+            toggle(&mut app, false);
+        }
+        // Every 200 ms, counting only the manual toggles
+        assert_eq!(app.world.resource::<Combo>().count, 5);
+    }
+
+    #[test]
+    fn combo_breaks_when_idle() {
+        let mut app = combo_app();
+        for _ in 0..12 {
+            toggle(&mut app, true);
+        }
+        let combo = app.world.resource::<Combo>();
+        assert_eq!((combo.count, combo.best), (12, 12));
+        // Toggling like clockwork is a rhythm
+        assert!(combo.in_rhythm);
+        assert_eq!(combo.multiplier(&ComboSettings::default()), 3);
+
+        idle(&mut app, 14);
+        assert_eq!(app.world.resource::<Combo>().count, 12);
+        idle(&mut app, 2);
+        let combo = app.world.resource::<Combo>();
+        assert_eq!((combo.count, combo.best), (0, 12));
+        assert!(!combo.in_rhythm);
+        assert_eq!(combo.multiplier(&ComboSettings::default()), 1);
+
+        // The next toggle starts a new combo
+        toggle(&mut app, true);
+        assert_eq!(app.world.resource::<Combo>().count, 1);
+    }
+}
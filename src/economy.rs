@@ -0,0 +1,435 @@
+use bevy::{math::vec3, prelude::*, sprite::Anchor, utils::HashMap};
+
+use crate::{
+    achievements::{AchievementQueue, AchievementStyle},
+    bitmap_font::BitmapFont,
+    click_analyzer::ClickAnalyzer,
+    combo::{Combo, ComboSettings},
+    localization::Localization,
+    replay::PlayerInput,
+    storage::{load_key_values, parse_value, save_key_values},
+    wall::{fitting_number, HorizontalAlign, WallFont, WallText, TOP_RIGHT_DIGIT_X},
+    LightToggled, ToggleLight,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Upgrade {
+    /// Flips the switch on its own, faster with every level
+    AutoToggler,
+    /// Every manual toggle counts once more per level
+    MultiClick,
+    /// More lumens per toggle, and while the light is on
+    BrighterBulbs,
+    /// Another switch on the wall, adding to every toggle and passive income
+    ExtraSwitch,
+}
+
+impl Upgrade {
+    pub const ALL: [Upgrade; 4] = [
+        Upgrade::AutoToggler,
+        Upgrade::MultiClick,
+        Upgrade::BrighterBulbs,
+        Upgrade::ExtraSwitch,
+    ];
+
+    /// Identifies the upgrade in saved files.
+    fn id(self) -> &'static str {
+        match self {
+            Upgrade::AutoToggler => "auto_toggler",
+            Upgrade::MultiClick => "multi_click",
+            Upgrade::BrighterBulbs => "brighter_bulbs",
+            Upgrade::ExtraSwitch => "extra_switch",
+        }
+    }
+
+    pub fn name(self, localization: &Localization) -> &str {
+        localization.get(match self {
+            Upgrade::AutoToggler => "upgrade.auto_toggler",
+            Upgrade::MultiClick => "upgrade.multi_click",
+            Upgrade::BrighterBulbs => "upgrade.brighter_bulbs",
+            Upgrade::ExtraSwitch => "upgrade.extra_switch",
+        })
+    }
+
+    fn base_cost(self) -> f64 {
+        match self {
+            Upgrade::AutoToggler => 50.0,
+            Upgrade::MultiClick => 20.0,
+            Upgrade::BrighterBulbs => 100.0,
+            Upgrade::ExtraSwitch => 500.0,
+        }
+    }
+
+    fn key(self) -> KeyCode {
+        match self {
+            Upgrade::AutoToggler => KeyCode::Key1,
+            Upgrade::MultiClick => KeyCode::Key2,
+            Upgrade::BrighterBulbs => KeyCode::Key3,
+            Upgrade::ExtraSwitch => KeyCode::Key4,
+        }
+    }
+}
+
+/// Lumens are earned by toggling the light and spent on [`Upgrade`]s.
+#[derive(Resource, Clone, Default)]
+pub struct Economy {
+    pub lumens: f64,
+    pub total_lumens: f64,
+    levels: [u32; Upgrade::ALL.len()],
+    auto_toggle_timer: Option<Timer>,
+}
+
+impl Economy {
+    pub fn load(file_name: &str) -> Self {
+        Self::from_values(&load_key_values(file_name))
+    }
+
+    pub fn save(&self, file_name: &str) {
+        save_key_values(file_name, self.to_values());
+    }
+
+    /// Reads the `key = value` pairs of [`Economy::to_values`]. Missing values are zero.
+    pub fn from_values(values: &HashMap<String, String>) -> Self {
+        let mut economy = Self {
+            lumens: parse_value(values, "lumens").unwrap_or_default(),
+            total_lumens: parse_value(values, "total_lumens").unwrap_or_default(),
+            ..default()
+        };
+        for upgrade in Upgrade::ALL {
+            economy.levels[upgrade as usize] =
+                parse_value(values, &format!("level.{}", upgrade.id())).unwrap_or_default();
+        }
+        economy.reset_auto_toggler();
+        economy
+    }
+
+    pub fn to_values(&self) -> Vec<(String, String)> {
+        let mut values = vec![
+            ("lumens".to_string(), self.lumens.to_string()),
+            ("total_lumens".to_string(), self.total_lumens.to_string()),
+        ];
+        values.extend(Upgrade::ALL.map(|upgrade| {
+            (
+                format!("level.{}", upgrade.id()),
+                self.level(upgrade).to_string(),
+            )
+        }));
+        values
+    }
+
+    pub fn level(&self, upgrade: Upgrade) -> u32 {
+        self.levels[upgrade as usize]
+    }
+
+    /// Every level costs 15% more than the previous one.
+    pub fn cost(&self, upgrade: Upgrade) -> f64 {
+        (upgrade.base_cost() * 1.15f64.powi(self.level(upgrade) as i32)).ceil()
+    }
+
+    pub fn lumens_per_toggle(&self, manual: bool) -> f64 {
+        let clicks = if manual {
+            1 + self.level(Upgrade::MultiClick)
+        } else {
+            1
+        };
+        let switches = 1 + self.level(Upgrade::ExtraSwitch);
+        (clicks * switches) as f64 * self.brightness()
+    }
+
+    /// Lumens per second, independent of toggling.
+    pub fn passive_income(&self, light_on: bool) -> f64 {
+        let switches = 0.5 * self.level(Upgrade::ExtraSwitch) as f64;
+        let bulbs = if light_on {
+            self.level(Upgrade::BrighterBulbs) as f64
+        } else {
+            0.0
+        };
+        (switches + bulbs) * self.brightness()
+    }
+
+    fn brightness(&self) -> f64 {
+        1.0 + 0.5 * self.level(Upgrade::BrighterBulbs) as f64
+    }
+
+    fn earn(&mut self, lumens: f64) {
+        self.lumens += lumens;
+        self.total_lumens += lumens;
+    }
+
+    fn buy(&mut self, upgrade: Upgrade) -> bool {
+        let cost = self.cost(upgrade);
+        if self.lumens < cost {
+            return false;
+        }
+        self.lumens -= cost;
+        self.levels[upgrade as usize] += 1;
+        if upgrade == Upgrade::AutoToggler {
+            self.reset_auto_toggler();
+        }
+        true
+    }
+
+    /// Restarts the auto-toggler at the speed of its level.
+    fn reset_auto_toggler(&mut self) {
+        let level = self.level(Upgrade::AutoToggler);
+        self.auto_toggle_timer =
+            (level > 0).then(|| Timer::from_seconds(2.0 / level as f32, TimerMode::Repeating));
+    }
+}
+
+/// Marks the [`WallText`] showing the current lumens.
+#[derive(Component)]
+pub struct LumensDisplay;
+
+/// Marks the upgrade shop panel.
+#[derive(Component)]
+pub struct ShopPanel;
+
+#[derive(Component)]
+pub struct ExtraSwitch;
+
+pub fn setup_economy(
+    mut commands: Commands,
+    wall_font: Res<WallFont>,
+    achievement_style: Res<AchievementStyle>,
+) {
+    commands.init_resource::<Economy>();
+    commands
+        .spawn(WallText {
+            text: String::new(),
+            font: wall_font.font.clone(),
+            position: IVec2::new(TOP_RIGHT_DIGIT_X as i32, 13),
+            horizontal_align: HorizontalAlign::Right,
+            visible: true,
+            roll: None,
+        })
+        .insert(LumensDisplay);
+
+    let text_style = TextStyle {
+        font_size: 12.0,
+        ..achievement_style.text_style.clone()
+    };
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section("", text_style),
+            text_anchor: Anchor::TopLeft,
+            ..default()
+        })
+        .insert(ShopPanel);
+}
+
+pub fn economy_toggle_update(
+    mut economy: ResMut<Economy>,
+    mut toggles: EventReader<LightToggled>,
+    mut achievement_queue: ResMut<AchievementQueue>,
+    combo: Res<Combo>,
+    combo_settings: Res<ComboSettings>,
+    click_analyzer: Res<ClickAnalyzer>,
+    player_input: Res<PlayerInput>,
+) {
+    let excluded = click_analyzer.excluded(&player_input);
+    for toggle in toggles.read() {
+        if toggle.manual && excluded {
+            continue;
+        }
+        let mut lumens = economy.lumens_per_toggle(toggle.manual);
+        if toggle.manual {
+            lumens *= combo.multiplier(&combo_settings) as f64;
+        }
+        economy.earn(lumens);
+    }
+
+    if economy.total_lumens >= 1_000_000.0 {
+        achievement_queue.unlock("Lumen millionaire");
+    }
+}
+
+/// Earns the passive income and runs the auto-toggler. Runs on the fixed timestep, ahead of the
+/// frame's input, so that a replay ends up with the same lumens however its frames are cut.
+pub fn economy_fixed_update(
+    mut economy: ResMut<Economy>,
+    mut toggle_requests: EventWriter<ToggleLight>,
+    time: Res<Time>,
+    query_light: Query<&DirectionalLight>,
+) {
+    let light_on = query_light.iter().any(|light| light.illuminance > 0.0);
+    let passive = economy.passive_income(light_on) * time.delta_seconds_f64();
+    if passive > 0.0 {
+        economy.earn(passive);
+    }
+
+    if let Some(timer) = &mut economy.auto_toggle_timer {
+        for _ in 0..timer.tick(time.delta()).times_finished_this_tick() {
+            toggle_requests.send(ToggleLight);
+        }
+    }
+}
+
+pub fn economy_shop_update(
+    mut economy: ResMut<Economy>,
+    player_input: Res<PlayerInput>,
+    mut achievement_queue: ResMut<AchievementQueue>,
+) {
+    for upgrade in Upgrade::ALL {
+        if !player_input.just_pressed(upgrade.key()) || !economy.buy(upgrade) {
+            continue;
+        }
+        achievement_queue.unlock("Shopaholic");
+        if upgrade == Upgrade::AutoToggler {
+            achievement_queue.unlock("Look ma, no hands");
+        }
+    }
+}
+
+/// Puts a switch on the wall for every [`Upgrade::ExtraSwitch`] level, whether it was just bought
+/// or loaded with the profile.
+pub fn extra_switch_update(
+    mut commands: Commands,
+    economy: Res<Economy>,
+    switches: Query<(), With<ExtraSwitch>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let spawned = switches.iter().count() as u32;
+    for index in spawned + 1..=economy.level(Upgrade::ExtraSwitch) {
+        commands
+            .spawn(PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Box::from_corners(
+                    vec3(-0.1, -0.15, 0.0),
+                    vec3(0.1, 0.15, 0.05),
+                ))),
+                material: materials.add(Color::WHITE.into()),
+                transform: Transform::from_xyz(0.2 + 0.3 * index as f32, 0.0, 0.0),
+                ..default()
+            })
+            .insert(ExtraSwitch);
+    }
+}
+
+pub fn economy_display_update(
+    economy: Res<Economy>,
+    fonts: Res<Assets<BitmapFont>>,
+    localization: Res<Localization>,
+    query_light: Query<&DirectionalLight>,
+    query_ortho: Query<&OrthographicProjection>,
+    mut lumens_display: Query<&mut WallText, With<LumensDisplay>>,
+    mut shop_panel: Query<(&mut Text, &mut Transform), With<ShopPanel>>,
+) {
+    for mut text in lumens_display.iter_mut() {
+        let Some(font) = fonts.get(&text.font) else {
+            continue;
+        };
+        let lumens = fitting_number(font, &text, economy.lumens as u64, "lm");
+        if text.text != lumens {
+            text.text = lumens;
+        }
+    }
+
+    let Ok(ortho) = query_ortho.get_single() else {
+        return;
+    };
+    let light_on = query_light.iter().any(|light| light.illuminance > 0.0);
+    for (mut text, mut transform) in shop_panel.iter_mut() {
+        transform.translation = vec3(ortho.area.min.x + 10.0, ortho.area.max.y - 10.0, 0.0);
+        if !economy.is_changed() && !localization.is_changed() {
+            continue;
+        }
+        let mut panel = localization.format(
+            "economy.panel",
+            &[
+                ("lumens", &format!("{:.0}", economy.lumens.floor())),
+                (
+                    "income",
+                    &format!("{:.1}", economy.passive_income(light_on)),
+                ),
+            ],
+        );
+        panel += "\n\n";
+        for (i, upgrade) in Upgrade::ALL.into_iter().enumerate() {
+            panel += &format!(
+                "[{}] {} {} - {:.0}\n",
+                i + 1,
+                upgrade.name(&localization),
+                economy.level(upgrade),
+                economy.cost(upgrade)
+            );
+        }
+        text.sections[0].value = panel;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    #[derive(Resource, Default)]
+    struct AutoToggles(usize);
+
+    fn count_auto_toggles(mut requests: EventReader<ToggleLight>, mut count: ResMut<AutoToggles>) {
+        count.0 += requests.read().count();
+    }
+
+    /// Lumens and auto-toggles after running the economy with these frame times.
+    fn run_economy(frame_times: impl IntoIterator<Item = Duration>) -> (f64, usize) {
+        let mut economy = Economy::default();
+        economy.levels[Upgrade::AutoToggler as usize] = 3;
+        economy.levels[Upgrade::BrighterBulbs as usize] = 1;
+        economy.levels[Upgrade::ExtraSwitch as usize] = 1;
+        economy.reset_auto_toggler();
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(economy)
+            .init_resource::<AutoToggles>()
+            .add_event::<ToggleLight>()
+            .add_systems(FixedUpdate, economy_fixed_update)
+            .add_systems(Update, count_auto_toggles);
+        app.world.spawn(DirectionalLight {
+            illuminance: 10000.0,
+            ..default()
+        });
+        app.update();
+        for frame_time in frame_times {
+            app.insert_resource(TimeUpdateStrategy::ManualDuration(frame_time));
+            app.update();
+        }
+        (
+            app.world.resource::<Economy>().lumens,
+            app.world.resource::<AutoToggles>().0,
+        )
+    }
+
+    #[test]
+    fn frame_times_dont_change_the_outcome() {
+        let even = run_economy(vec![Duration::from_millis(10); 1000]);
+        let uneven = run_economy(
+            [7, 23, 1, 19]
+                .map(Duration::from_millis)
+                .into_iter()
+                .cycle()
+                .take(800),
+        );
+        assert_eq!(even, uneven);
+        // 10 seconds of (0.5 + 1) lm/s from the extra switch and the lit bulbs, 1.5 times as
+        // bright
+        assert!((even.0 - 22.5).abs() < 1e-9);
+        // A toggle every 2/3 s, rounded up to f32, so the 15th comes just too late
+        assert_eq!(even.1, 14);
+    }
+
+    #[test]
+    fn saved_values_load_the_same() {
+        let mut economy = Economy::default();
+        economy.earn(1234.5);
+        economy.levels[Upgrade::AutoToggler as usize] = 2;
+        economy.levels[Upgrade::ExtraSwitch as usize] = 3;
+        let values = economy.to_values().into_iter().collect::<HashMap<_, _>>();
+        let loaded = Economy::from_values(&values);
+        assert_eq!(loaded.lumens, 1234.5);
+        assert_eq!(loaded.total_lumens, 1234.5);
+        assert_eq!(loaded.levels, economy.levels);
+        assert!(loaded.auto_toggle_timer.is_some());
+    }
+}
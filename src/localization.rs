@@ -0,0 +1,229 @@
+use ab_glyph::Font as _;
+use bevy::{prelude::*, utils::HashMap};
+use std::{collections::BTreeSet, fmt, fs};
+
+use crate::{
+    achievements::AchievementStyle,
+    settings::Settings,
+    storage::{data_dir, parse_key_values},
+};
+
+pub const LANGUAGES: [&str; 2] = ["en", "de"];
+/// Directory in the [`data_dir`] with fonts for scripts the embedded font can't render
+const FALLBACK_FONTS_DIR: &str = "fonts";
+
+fn locale_file(language: &str) -> &'static str {
+    match language {
+        "de" => include_str!("locales/de.txt"),
+        _ => include_str!("locales/en.txt"),
+    }
+}
+
+fn parse_locale(content: &str) -> HashMap<String, String> {
+    let mut strings = parse_key_values(content);
+    for value in strings.values_mut() {
+        *value = value.replace("\\n", "\n");
+    }
+    strings
+}
+
+/// User-facing texts in the language chosen in the [`Settings`].
+#[derive(Resource)]
+pub struct Localization {
+    pub language: String,
+    strings: HashMap<String, String>,
+    /// English texts, for keys the language lacks
+    fallback: HashMap<String, String>,
+}
+
+impl Localization {
+    pub fn new(language: &str) -> Self {
+        Self {
+            language: language.to_string(),
+            strings: parse_locale(locale_file(language)),
+            fallback: parse_locale(locale_file(LANGUAGES[0])),
+        }
+    }
+
+    /// The text for `key`, or the key itself if no language has it.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.strings
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map_or(key, String::as_str)
+    }
+
+    /// [`Localization::get`] with every `{name}` replaced by its value.
+    pub fn format(&self, key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+        args.iter()
+            .fold(self.get(key).to_string(), |text, (name, value)| {
+                text.replace(&format!("{{{name}}}"), &value.to_string())
+            })
+    }
+
+    /// Achievements are stored by their English name, which also is the fallback.
+    pub fn achievement<'a>(&'a self, name: &'a str) -> &'a str {
+        let key = format!("achievement.{}", slug(name));
+        self.strings
+            .get(&key)
+            .or_else(|| self.fallback.get(&key))
+            .map_or(name, String::as_str)
+    }
+
+    /// Every character the texts of the language use.
+    fn characters(&self) -> BTreeSet<char> {
+        self.strings
+            .values()
+            .chain(self.fallback.values())
+            .flat_map(|text| text.chars())
+            .filter(|c| !c.is_whitespace())
+            .collect()
+    }
+}
+
+/// `Time flies when you're having fun` becomes `time_flies_when_you_re_having_fun`.
+fn slug(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Spells out characters the wall font has no glyphs for, like `ä` as `ae`.
+pub fn transliterate(text: &str, has_glyph: impl Fn(char) -> bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if has_glyph(c) {
+            result.push(c);
+            continue;
+        }
+        let replacement = match c {
+            'ä' => "ae",
+            'ö' => "oe",
+            'ü' => "ue",
+            'Ä' => "Ae",
+            'Ö' => "Oe",
+            'Ü' => "Ue",
+            'ß' => "ss",
+            _ => {
+                // Otherwise the font shows its replacement glyph
+                result.push(c);
+                continue;
+            }
+        };
+        result.push_str(replacement);
+    }
+    result
+}
+
+/// The embedded text font and the fonts to fall back to for scripts it can't render.
+#[derive(Resource)]
+pub struct UiFonts {
+    primary: Handle<Font>,
+    fallbacks: Vec<Handle<Font>>,
+    /// Font the texts are currently shown in
+    current: Handle<Font>,
+}
+
+pub fn setup_localization(
+    mut commands: Commands,
+    achievement_style: Res<AchievementStyle>,
+    mut fonts: ResMut<Assets<Font>>,
+) {
+    let dir = data_dir().join(FALLBACK_FONTS_DIR);
+    let mut paths = fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|extension| extension == "ttf" || extension == "otf")
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    let fallbacks = paths
+        .into_iter()
+        .filter_map(|path| {
+            let font = fs::read(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| Font::try_from_bytes(bytes).map_err(|err| err.to_string()));
+            match font {
+                Ok(font) => Some(fonts.add(font)),
+                Err(err) => {
+                    warn!("Could not load fallback font {path:?}: {err}");
+                    None
+                }
+            }
+        })
+        .collect();
+
+    let primary = achievement_style.text_style.font.clone();
+    commands.insert_resource(UiFonts {
+        current: primary.clone(),
+        primary,
+        fallbacks,
+    });
+}
+
+pub fn localization_update(settings: Res<Settings>, mut localization: ResMut<Localization>) {
+    if localization.language != settings.language {
+        *localization = Localization::new(&settings.language);
+    }
+}
+
+/// Checks that the text font has glyphs for the language and otherwise switches all texts to the
+/// first fallback font that has.
+pub fn localization_font_update(
+    localization: Res<Localization>,
+    fonts: Res<Assets<Font>>,
+    mut ui_fonts: ResMut<UiFonts>,
+    mut achievement_style: ResMut<AchievementStyle>,
+    mut texts: Query<&mut Text>,
+) {
+    let Some(primary) = fonts.get(&ui_fonts.primary) else {
+        // Not loaded yet
+        return;
+    };
+    let covers = |font: &Font, c: char| font.font.glyph_id(c).0 != 0;
+    let missing = localization
+        .characters()
+        .into_iter()
+        .filter(|&c| !covers(primary, c))
+        .collect::<String>();
+    let font = if missing.is_empty() {
+        ui_fonts.primary.clone()
+    } else {
+        let fallback = ui_fonts.fallbacks.iter().find(|fallback| {
+            fonts
+                .get(*fallback)
+                .is_some_and(|font| missing.chars().all(|c| covers(font, c)))
+        });
+        match fallback {
+            Some(fallback) => fallback.clone(),
+            None => {
+                warn!(
+                    "No font for `{missing}` of language {}, put one into {:?}",
+                    localization.language,
+                    data_dir().join(FALLBACK_FONTS_DIR)
+                );
+                ui_fonts.primary.clone()
+            }
+        }
+    };
+    if font == ui_fonts.current {
+        return;
+    }
+
+    let previous = std::mem::replace(&mut ui_fonts.current, font.clone());
+    achievement_style.text_style.font = font.clone();
+    for mut text in texts.iter_mut() {
+        for section in &mut text.sections {
+            if section.style.font == previous {
+                section.style.font = font.clone();
+            }
+        }
+    }
+}
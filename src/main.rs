@@ -1,19 +1,91 @@
-use achievements::{
-    achievement_update, setup_achievements, AchievementQueue, AchievementToBeAdded,
+use achievements::{achievement_update, setup_achievements, AchievementQueue};
+use announcements::{
+    announcement_output, score_announcement_update, Announcement, AnnouncementOutput,
+};
+use audio::{
+    audio_effects_update, audio_mixer_update, clock_sound_update, setup_audio, SynthSound, EAR_GAP,
 };
 use bevy::{
+    app::ScheduleRunnerPlugin,
+    audio::AddAudioSource,
     core_pipeline::clear_color::ClearColorConfig,
-    input::mouse::{MouseScrollUnit, MouseWheel},
+    input::InputSystem,
     math::vec3,
     prelude::*,
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::ExitCondition,
+    winit::WinitPlugin,
 };
 use bevy_hanabi::prelude::*;
+use bitmap_font::{BitmapFont, BitmapFontLoader};
+use camera::{camera_controls_update, camera_transform_update, setup_camera, OrbitCamera};
+use challenge::{
+    challenge_display_update, challenge_result_update, challenge_update, setup_challenge,
+};
+use cli::{CliError, Options, USAGE};
+use click_analyzer::{click_analyzer_update, setup_click_analyzer, ClickAnalyzer};
+use color_match::{color_match_display_update, color_match_update, setup_color_match};
+use combo::{combo_meter_update, combo_update, setup_combo, Combo, ComboSettings};
+use economy::{
+    economy_display_update, economy_fixed_update, economy_shop_update, economy_toggle_update,
+    extra_switch_update, setup_economy,
+};
+use localization::{
+    localization_font_update, localization_update, setup_localization, Localization,
+};
+use marquee::{marquee_update, setup_marquee};
+use menu::{
+    gallery_update, hide, menu_navigation_update, menu_panels_update, pause_time, setup_menu, show,
+    splash_update, unpause_time, GalleryPanel, GameState, PausePanel, SplashPanel,
+};
+use profiles::{
+    leaderboard_panel_update, profile_save, profile_selection_update, setup_profiles,
+    ActiveProfile, ProfilePanel, ProfileSelection,
+};
+use replay::{
+    player_input_update, replay_playback_start, replay_playback_time, replay_record, replay_save,
+    setup_replay, PlayerInput, ReplayPlayback, ReplayRecorder,
+};
+use settings::{
+    settings_apply, settings_menu_update, setup_settings, Settings, SettingsOverrides,
+    SettingsPanel,
+};
+use stats::{setup_stats, stats_panel_update, stats_update};
 use std::{
     f32::consts::*,
     path::{Path, PathBuf},
+    time::Duration,
+};
+use wall::{
+    score_display_update, setup_wall, wall_benchmark, wall_mesh_update, wall_pattern_update,
+    wall_renderer_update, wall_text_update, wall_tiles_update, WallBenchmark, WallRenderer,
+    WallTilePalette,
+};
+use wall_animation::{
+    wall_ripple_start, wall_ripple_update, wall_text_roll_update, wall_tile_flip_update,
 };
 
 mod achievements;
+mod announcements;
+mod audio;
+mod bitmap_font;
+mod camera;
+mod challenge;
+mod cli;
+mod click_analyzer;
+mod color_match;
+mod combo;
+mod economy;
+mod localization;
+mod marquee;
+mod menu;
+mod profiles;
+mod replay;
+mod settings;
+mod stats;
+mod storage;
+mod wall;
+mod wall_animation;
 
 // https://github.com/bevyengine/bevy/pull/10383
 #[doc(hidden)]
@@ -52,30 +124,250 @@ macro_rules! embedded_asset {
 }
 
 fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(CliError::Help) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let settings = Settings::load();
+    let overrides = SettingsOverrides::new(&options);
+    let benchmark = options.wall_benchmark;
+
     let mut app = App::new();
-    app.add_plugins(DefaultPlugins.set(WindowPlugin {
-        primary_window: Some(Window {
+    if options.headless {
+        app.add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }
+                    .into(),
+                })
+                .disable::<WinitPlugin>(),
+        )
+        .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::ZERO))
+        // The particle effects need a renderer, but their assets are still created
+        .init_asset::<EffectAsset>();
+    } else {
+        let mut window = Window {
             title: "AcaGameJam Winner 2023".to_string(),
             ..default()
-        }),
-        ..default()
-    }))
-    .add_plugins(HanabiPlugin)
-    //.add_plugins((bevy::diagnostic::LogDiagnosticsPlugin::default(), bevy::diagnostic::FrameTimeDiagnosticsPlugin))
-    .add_systems(Startup, (setup, setup_achievements))
-    .add_systems(
-        Update,
-        (
-            light_temperature_update,
-            light_switch_update,
-            wall_update,
-            achievement_update,
-            update_clock_hands,
-            clock_achievement_check,
-        ),
-    );
+        };
+        if let Some(size) = options.window_size {
+            window.resolution = size.into();
+        }
+        let settings = overrides.apply(&settings);
+        window.mode = settings.window_mode();
+        window.present_mode = settings.present_mode();
+        app.add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(window),
+            ..default()
+        }))
+        .add_plugins(HanabiPlugin);
+    }
+    let localization = Localization::new(&settings.language);
+    app.insert_resource(options)
+        .insert_resource(settings)
+        .insert_resource(overrides)
+        .insert_resource(localization)
+        .add_state::<GameState>()
+        .init_asset::<BitmapFont>()
+        .init_asset_loader::<BitmapFontLoader>()
+        .add_audio_source::<SynthSound>()
+        .add_event::<LightToggled>()
+        .add_event::<ToggleLight>()
+        .add_event::<Announcement>()
+        .init_resource::<AnnouncementOutput>()
+        //.add_plugins((bevy::diagnostic::LogDiagnosticsPlugin::default(), bevy::diagnostic::FrameTimeDiagnosticsPlugin))
+        .add_systems(
+            Startup,
+            (
+                setup_click_analyzer,
+                setup_replay,
+                (
+                    (setup, setup_achievements, setup_wall),
+                    apply_deferred,
+                    (
+                        setup_marquee,
+                        setup_economy,
+                        setup_combo,
+                        setup_stats,
+                        setup_profiles,
+                        setup_challenge,
+                        setup_color_match,
+                        setup_settings,
+                        setup_menu,
+                        setup_audio,
+                        setup_camera,
+                        setup_localization,
+                    ),
+                )
+                    .chain(),
+            ),
+        )
+        .add_systems(OnExit(GameState::Splash), hide::<SplashPanel>)
+        .add_systems(OnEnter(GameState::MainMenu), show::<ProfilePanel>)
+        .add_systems(OnExit(GameState::MainMenu), hide::<ProfilePanel>)
+        .add_systems(Startup, pause_time)
+        .add_systems(OnEnter(GameState::Playing), unpause_time)
+        .add_systems(OnExit(GameState::Playing), pause_time)
+        .add_systems(OnEnter(GameState::Paused), show::<PausePanel>)
+        .add_systems(OnExit(GameState::Paused), hide::<PausePanel>)
+        .add_systems(OnEnter(GameState::Gallery), show::<GalleryPanel>)
+        .add_systems(OnExit(GameState::Gallery), hide::<GalleryPanel>)
+        .add_systems(OnEnter(GameState::Settings), show::<SettingsPanel>)
+        .add_systems(OnExit(GameState::Settings), hide::<SettingsPanel>)
+        .add_systems(
+            PreUpdate,
+            (
+                player_input_update,
+                replay_record.run_if(
+                    resource_exists::<ReplayRecorder>()
+                        .and_then(resource_exists::<ActiveProfile>()),
+                ),
+            )
+                .chain()
+                .after(InputSystem),
+        )
+        .add_systems(
+            Update,
+            (
+                replay_playback_start.run_if(
+                    resource_exists::<ReplayPlayback>().and_then(in_state(GameState::Splash)),
+                ),
+                (
+                    splash_update.run_if(
+                        in_state(GameState::Splash)
+                            .and_then(not(resource_exists::<ReplayPlayback>())),
+                    ),
+                    menu_navigation_update,
+                    menu_panels_update,
+                    gallery_update.run_if(in_state(GameState::Gallery)),
+                ),
+                (
+                    settings_menu_update.run_if(in_state(GameState::Settings)),
+                    settings_apply.run_if(
+                        resource_changed::<Settings>()
+                            .or_else(resource_changed::<SettingsOverrides>()),
+                    ),
+                    localization_update.run_if(resource_changed::<Settings>()),
+                    localization_font_update.run_if(
+                        resource_changed::<Localization>().or_else(on_event::<AssetEvent<Font>>()),
+                    ),
+                )
+                    .chain()
+                    .before(wall_pattern_update),
+                profile_selection_update.run_if(
+                    resource_exists::<ProfileSelection>().and_then(in_state(GameState::MainMenu)),
+                ),
+                light_temperature_update.run_if(in_state(GameState::Playing)),
+                click_analyzer_update.before(light_switch_update),
+                light_switch_update.run_if(in_state(GameState::Playing)),
+                (
+                    combo_update,
+                    combo_meter_update,
+                    economy_toggle_update,
+                    economy_shop_update,
+                    extra_switch_update,
+                    economy_display_update,
+                )
+                    .chain()
+                    .after(light_switch_update)
+                    .run_if(in_state(GameState::Playing)),
+                (
+                    wall_pattern_update.run_if(resource_changed::<WallTilePalette>()),
+                    wall_renderer_update.run_if(resource_changed::<WallRenderer>()),
+                    apply_deferred,
+                    score_display_update.run_if(
+                        resource_changed::<Score>().or_else(on_event::<AssetEvent<BitmapFont>>()),
+                    ),
+                    marquee_update,
+                    wall_text_roll_update,
+                    wall_text_update,
+                    wall_tiles_update,
+                    wall_mesh_update,
+                    wall_ripple_start,
+                    apply_deferred,
+                    wall_tile_flip_update,
+                    wall_ripple_update,
+                )
+                    .chain(),
+                achievement_update,
+                (
+                    stats_update.run_if(in_state(GameState::Playing)),
+                    stats_panel_update,
+                )
+                    .chain(),
+                leaderboard_panel_update,
+                (
+                    challenge_update,
+                    challenge_display_update,
+                    challenge_result_update,
+                )
+                    .chain()
+                    .after(light_switch_update)
+                    .after(light_temperature_update)
+                    .run_if(in_state(GameState::Playing)),
+                (color_match_update, color_match_display_update)
+                    .chain()
+                    .after(light_temperature_update)
+                    .run_if(in_state(GameState::Playing)),
+                update_clock_hands,
+                clock_achievement_check,
+                (
+                    camera_controls_update
+                        .run_if(in_state(GameState::Playing).or_else(in_state(GameState::Paused))),
+                    camera_transform_update,
+                )
+                    .chain(),
+                (
+                    audio_effects_update.after(light_temperature_update),
+                    audio_mixer_update,
+                    clock_sound_update,
+                ),
+                (
+                    score_announcement_update.run_if(in_state(GameState::Playing)),
+                    announcement_output,
+                )
+                    .chain()
+                    .after(achievement_update),
+            ),
+        )
+        .add_systems(
+            FixedUpdate,
+            economy_fixed_update.run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            Last,
+            (
+                profile_save.run_if(
+                    resource_exists::<ActiveProfile>()
+                        .and_then(not(resource_exists::<ReplayPlayback>())),
+                ),
+                replay_save.run_if(resource_exists::<ReplayRecorder>()),
+                replay_playback_time.run_if(resource_exists::<ReplayPlayback>()),
+            ),
+        );
+    if benchmark {
+        app.init_resource::<WallBenchmark>()
+            .add_systems(Update, wall_benchmark.before(wall_renderer_update));
+    }
     embedded_asset!(app, "./PublicPixel-z84yD.ttf");
     embedded_asset!(app, "./achievement.ogg");
+    embedded_asset!(app, "./wall_font.wallfont");
     app.run();
 }
 
@@ -84,43 +376,65 @@ struct ColorTemperature {
     value: f32,
 }
 
+impl ColorTemperature {
+    /// Dimmer movement per scroll line at the default scroll sensitivity
+    const STEP: f32 = 0.05;
+
+    /// Color temperature of the light for a dimmer value between 0 and 1.
+    fn kelvin(value: f32) -> f32 {
+        3000.0 + value * 4000.0
+    }
+}
+
 #[derive(Component)]
 struct LightSwitch;
 
 #[derive(Component)]
-struct WallTile {
-    x: usize,
-    y: usize,
-}
+struct Clock;
 
-#[derive(Resource)]
-struct WallTilePalette {
-    materials: Vec<Handle<StandardMaterial>>,
-    number_material: Handle<StandardMaterial>,
-    seed: u64,
+/// Sent whenever the light switch is flipped.
+#[derive(Event)]
+struct LightToggled {
+    /// Flipped by the player rather than by a [`ToggleLight`] request
+    manual: bool,
 }
 
+/// Asks for the light switch to be flipped without player input.
+#[derive(Event)]
+struct ToggleLight;
+
 #[derive(Resource)]
 struct Score {
-    value: u32,
+    value: u64,
 }
 
-const WALL_SIZE_X: f32 = 18.0;
-const WALL_SIZE_Y: f32 = 5.0;
-const TILE_SIZE: f32 = 0.2;
 const CLOCK_RADIUS: f32 = 0.4;
 const CLOCK_MINUTE_HAND_LENGTH: f32 = CLOCK_RADIUS * 0.9;
 const CLOCK_HOUR_HAND_LENGTH: f32 = CLOCK_RADIUS * 0.5;
+const CLOCK_SECOND_HAND_LENGTH: f32 = CLOCK_RADIUS * 0.95;
 
 #[derive(Component, Copy, Clone)]
 enum ClockHand {
+    Second,
     Minute,
     Hour,
 }
 
+/// Wall clock that advances with the real time, so it keeps up with the local time while the
+/// game is paused. Replays step the real time frame by frame, so a replay sees the time it was
+/// recorded at.
 #[derive(Resource)]
-struct StartupWallClockTime {
-    time: chrono::DateTime<chrono::Local>,
+struct WallClock {
+    /// Wall clock time at real time zero, shifted by `--clock` and replays
+    start: chrono::DateTime<chrono::Local>,
+}
+
+impl WallClock {
+    fn now(&self, time: &Time<Real>) -> chrono::DateTime<chrono::Local> {
+        self.start
+            + chrono::Duration::from_std(time.elapsed())
+                .unwrap_or_else(|_| chrono::Duration::zero())
+    }
 }
 
 /// set up a simple 3D scene
@@ -129,42 +443,13 @@ fn setup(
     // asset_server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    options: Res<Options>,
+    settings: Res<Settings>,
 ) {
-    commands.insert_resource(Score { value: 0 });
-    commands.insert_resource(AchievementQueue::default());
-
-    // wall
-    let mesh = meshes.add(shape::Plane::from_size(TILE_SIZE).into());
-    commands.insert_resource(WallTilePalette {
-        materials: [
-            Color::hex("#0C356A").unwrap(),
-            Color::hex("#0174BE").unwrap(),
-            Color::hex("#FFC436").unwrap(),
-        ]
-        .into_iter()
-        .map(|color| materials.add(color.into()))
-        .collect(),
-        number_material: materials.add(Color::hex("#FFF0CE").unwrap().into()),
-        seed: rand::random::<u64>(),
+    commands.insert_resource(Score {
+        value: options.score.unwrap_or_default(),
     });
-
-    for x in 0..=(WALL_SIZE_X / TILE_SIZE) as usize {
-        for y in 0..=(WALL_SIZE_Y / TILE_SIZE) as usize {
-            commands
-                .spawn(PbrBundle {
-                    mesh: mesh.clone(),
-                    // material:
-                    transform: Transform::from_rotation(Quat::from_rotation_x(FRAC_PI_2))
-                        .with_translation(vec3(
-                            x as f32 * TILE_SIZE - WALL_SIZE_X / 2.0,
-                            y as f32 * TILE_SIZE - WALL_SIZE_Y / 2.0,
-                            0.0,
-                        )),
-                    ..default()
-                })
-                .insert(WallTile { x, y });
-        }
-    }
+    commands.insert_resource(AchievementQueue::default());
 
     let switch_material = materials.add(Color::WHITE.into());
     // switch
@@ -219,6 +504,7 @@ fn setup(
                 .with_rotation(Quat::from_rotation_x(FRAC_PI_2)),
             ..default()
         })
+        .insert(Clock)
         .with_children(|builder| {
             let hand_material = materials.add(StandardMaterial {
                 cull_mode: None,
@@ -241,7 +527,7 @@ fn setup(
                 .spawn(PbrBundle {
                     mesh: meshes.add(Mesh::from(shape::Plane::default())),
                     material: hand_material.clone(),
-                    transform: clock_hand_transform(ClockHand::Minute),
+                    transform: clock_hand_transform(ClockHand::Minute, chrono::Local::now()),
                     ..default()
                 })
                 .insert(ClockHand::Minute);
@@ -250,13 +536,25 @@ fn setup(
                 .spawn(PbrBundle {
                     mesh: meshes.add(Mesh::from(shape::Plane::default())),
                     material: hand_material.clone(),
-                    transform: clock_hand_transform(ClockHand::Minute),
+                    transform: clock_hand_transform(ClockHand::Minute, chrono::Local::now()),
                     ..default()
                 })
                 .insert(ClockHand::Hour);
+            // Second hand, jumping in time with the tick of the clock sound
+            builder
+                .spawn(PbrBundle {
+                    mesh: meshes.add(Mesh::from(shape::Plane::default())),
+                    material: materials.add(StandardMaterial {
+                        cull_mode: None,
+                        ..Color::rgb(0.8, 0.2, 0.2).into()
+                    }),
+                    transform: clock_hand_transform(ClockHand::Second, chrono::Local::now()),
+                    ..default()
+                })
+                .insert(ClockHand::Second);
         });
-    commands.insert_resource(StartupWallClockTime {
-        time: chrono::Local::now(),
+    commands.insert_resource(WallClock {
+        start: options.clock.unwrap_or_else(chrono::Local::now),
     });
 
     // light
@@ -267,7 +565,8 @@ fn setup(
             shadows_enabled: true,
             ..Default::default()
         },
-        transform: Transform::from_rotation(Quat::from_euler(
+        // Directional lights only use the rotation, the translation places the lamp's hum
+        transform: Transform::from_xyz(0.5, 2.5, 1.5).with_rotation(Quat::from_euler(
             EulerRot::XYZ,
             -std::f32::consts::TAU * 0.15,
             -std::f32::consts::TAU / 16.0,
@@ -277,10 +576,14 @@ fn setup(
     });
 
     // camera
-    commands.spawn(Camera3dBundle {
-        transform: Transform::from_xyz(-0.5, 1.0, 4.0).looking_at(Vec3::ZERO, Vec3::Y),
-        ..default()
-    });
+    commands.spawn((
+        Camera3dBundle {
+            transform: Transform::from_xyz(-0.5, 1.0, 4.0).looking_at(Vec3::ZERO, Vec3::Y),
+            ..default()
+        },
+        OrbitCamera::default(),
+        SpatialListener::new(EAR_GAP),
+    ));
 
     // 2d camera
     let mut camera2d = Camera2dBundle {
@@ -293,51 +596,63 @@ fn setup(
         },
         ..default()
     };
-    // camera2d.projection.scale = 1.0;
-    // camera2d.projection.scaling_mode = ScalingMode::FixedVertical(1.);
+    camera2d.projection.scaling_mode = settings.ui_scaling.scaling_mode();
     commands.spawn(camera2d);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn light_switch_update(
     mut score: ResMut<Score>,
-    mouse_input: Res<Input<MouseButton>>,
+    player_input: Res<PlayerInput>,
     mut query_light: Query<&mut DirectionalLight>,
     mut query_switch: Query<&mut Transform, With<LightSwitch>>,
     mut achievement_queue: ResMut<AchievementQueue>,
+    mut toggled: EventWriter<LightToggled>,
+    mut toggle_requests: EventReader<ToggleLight>,
+    click_analyzer: Res<ClickAnalyzer>,
+    combo: Res<Combo>,
+    combo_settings: Res<ComboSettings>,
 ) {
-    if mouse_input.just_released(MouseButton::Middle) {
+    let manual_toggles = (0..player_input.switch_releases()).map(|_| true);
+    let requested_toggles = toggle_requests.read().map(|_| false);
+    for manual in manual_toggles.chain(requested_toggles) {
         for mut light in query_light.iter_mut() {
             if light.illuminance > 0.0 {
                 light.illuminance = 0.0;
             } else {
                 light.illuminance = 10000.0;
             }
-            score.value += 1;
+            toggled.send(LightToggled { manual });
+            if manual && click_analyzer.excluded(&player_input) {
+                continue;
+            }
+            // Manual toggles score the whole combo multiplier, automatic ones a single point.
+            // The multiplier is a whole number, so the score needs no rounding.
+            let points = if manual {
+                u64::from(combo.multiplier(&combo_settings))
+            } else {
+                1
+            };
+            let previous = score.value;
+            score.value = score.value.saturating_add(points);
 
-            if score.value == 1 {
-                achievement_queue.queue.push_back(AchievementToBeAdded {
-                    text: "Lights on".to_string(),
-                });
+            if previous == 0 {
+                achievement_queue.unlock("Lights on");
             }
 
-            if score.value == 100 {
-                achievement_queue.queue.push_back(AchievementToBeAdded {
-                    text: "But I wanted cookies...".to_string(),
-                });
+            // Robots get their own achievement instead
+            if previous < 100 && score.value >= 100 && !click_analyzer.is_suspicious() {
+                achievement_queue.unlock("But I wanted cookies...");
             }
         }
     }
     for mut switch in query_switch.iter_mut() {
-        switch.translation.z = if mouse_input.pressed(MouseButton::Middle) {
-            -0.05
-        } else {
-            0.0
-        };
+        switch.translation.z = if player_input.switch_held { -0.05 } else { 0.0 };
     }
 }
 
 fn light_temperature_update(
-    mut scroll_events: EventReader<MouseWheel>,
+    player_input: Res<PlayerInput>,
     mut query_light: Query<&mut DirectionalLight>,
     mut query_switch: Query<&mut Transform, With<ColorTemperature>>,
     mut query_temperature: Query<&mut ColorTemperature>,
@@ -345,20 +660,12 @@ fn light_temperature_update(
 ) {
     let mut query_temperature = query_temperature.single_mut();
 
-    for event in scroll_events.read() {
+    for amount in player_input.dimmer_moves() {
         if !achievement_queue.was_dimmer_used {
             achievement_queue.was_dimmer_used = true;
-            achievement_queue.queue.push_back(AchievementToBeAdded {
-                text: "So colorful *_*".to_string(),
-            });
+            achievement_queue.unlock("So colorful *_*");
         }
-        query_temperature.value += match event.unit {
-            MouseScrollUnit::Line => event.y,
-            MouseScrollUnit::Pixel => {
-                println!("pixel {}", event.y);
-                event.y * 10.0
-            }
-        } * 0.05;
+        query_temperature.value += amount * ColorTemperature::STEP;
     }
     query_temperature.value = f32::clamp(query_temperature.value, 0.0, 1.0);
 
@@ -368,25 +675,26 @@ fn light_temperature_update(
     }
 
     for mut light in query_light.iter_mut() {
-        light.color = color_temperature_to_rgb(3000.0 + query_temperature.value * 4000.0)
+        light.color = color_temperature_to_rgb(ColorTemperature::kelvin(query_temperature.value))
             .extend(1.0)
             .into();
     }
 }
 
+// The published coefficients, rounded to f32 precision
 fn color_temperature_to_rgb(temperature: f32) -> Vec3 {
     // Values from: http://blenderartists.org/forum/showthread.php?270332-OSL-Goodness&p=2268693&viewfull=1#post2268693
     let m = if temperature <= 6500.0 {
         Mat3::from_cols(
-            vec3(0.0, -2902.1955373783176, -8257.7997278925690),
-            vec3(0.0, 1669.5803561666639, 2575.2827530017594),
-            vec3(1.0, 1.3302673723350029, 1.8993753891711275),
+            vec3(0.0, -2902.1955, -8257.8),
+            vec3(0.0, 1669.5803, 2575.2828),
+            vec3(1.0, 1.3302674, 1.8993754),
         )
     } else {
         Mat3::from_cols(
-            vec3(1745.0425298314172, 1216.6168361476490, -8257.7997278925690),
-            vec3(-2666.3474220535695, -2173.1012343082230, 2575.2827530017594),
-            vec3(0.55995389139931482, 0.70381203140554553, 1.8993753891711275),
+            vec3(1745.0425, 1216.6168, -8257.8),
+            vec3(-2666.3474, -2173.1012, 2575.2828),
+            vec3(0.55995389, 0.703812, 1.8993754),
         )
     };
     let temperature = temperature.clamp(1000.0, 40000.0);
@@ -403,103 +711,20 @@ fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
     t * t * (3.0 - 2.0 * t)
 }
 
-fn wall_update(
-    mut wall_tiles: Query<(&WallTile, &mut Handle<StandardMaterial>)>,
-    palette: Res<WallTilePalette>,
-    score: Res<Score>,
+fn update_clock_hands(
+    wall_clock: Res<WallClock>,
+    time: Res<Time<Real>>,
+    mut query: Query<(&mut Transform, &ClockHand)>,
 ) {
-    use rand::Rng;
-    use rand::SeedableRng;
-
-    // Set random seed
-    let mut rng = rand::rngs::StdRng::seed_from_u64(palette.seed);
-
-    const PATTERN_SIZE: usize = 5;
-    // Clever Anke stuff: Make last color less likely than the others
-    let pattern = (0..PATTERN_SIZE * PATTERN_SIZE)
-        .map(|_| (rng.gen::<usize>() % (palette.materials.len() * 2 - 1)) % palette.materials.len())
-        .collect::<Vec<_>>();
-
-    let pattern_index = |tile: &WallTile| {
-        let x = ((tile.x % (PATTERN_SIZE * 2 - 2)) as i32 - PATTERN_SIZE as i32 + 2).abs() as usize;
-        let y = ((tile.y % (PATTERN_SIZE * 2 - 2)) as i32 - PATTERN_SIZE as i32 + 2).abs() as usize;
-        x + y * PATTERN_SIZE
-    };
-
-    let digit_patterns = include_str!("digits.txt")
-        .chars()
-        .filter_map(|c| match c {
-            ' ' => Some(false),
-            '\n' => None,
-            '.' => Some(true),
-            _ => unreachable!("Invalid digit pattern"),
-        })
-        .collect::<Vec<_>>();
-
-    let score_str = score.value.to_string();
-
-    for (tile, mut material) in wall_tiles.iter_mut() {
-        *material = if is_digit_tile(tile, &score_str, &digit_patterns) {
-            palette.number_material.clone()
-        } else {
-            palette.materials[pattern[pattern_index(tile)]].clone()
-        };
-    }
-}
-
-fn is_digit_tile(tile: &WallTile, digits: &str, digit_patterns: &[bool]) -> bool {
-    const TOP_RIGHT_DIGIT_X: usize = (WALL_SIZE_X / TILE_SIZE / 2.0) as usize + 13;
-    const TOP_RIGHT_DIGIT_Y: usize = (WALL_SIZE_Y / TILE_SIZE / 2.0) as usize + 7;
-    const DIGIT_SIZE_X: usize = 3;
-    const DIGIT_SIZE_Y: usize = 5;
-
-    // Are we in the digit area?
-    if tile.x > TOP_RIGHT_DIGIT_X
-        || tile.y > TOP_RIGHT_DIGIT_Y
-        || tile.y <= TOP_RIGHT_DIGIT_Y - DIGIT_SIZE_Y
-    {
-        return false;
-    }
-
-    // First determine in which digit we are
-    let digit_idx = (TOP_RIGHT_DIGIT_X - tile.x) / (DIGIT_SIZE_X + 1);
-    if digit_idx >= digits.len() {
-        return false;
-    }
-    let digit_index = digits.len() - 1 - digit_idx;
-
-    // Where inside this digit are we
-    let digit_x = (TOP_RIGHT_DIGIT_X - tile.x) % (DIGIT_SIZE_X + 1);
-    if digit_x == DIGIT_SIZE_X {
-        return false; // We're in the space between digits!
-    }
-    let digit_x = DIGIT_SIZE_X - digit_x - 1;
-    let digit_y = TOP_RIGHT_DIGIT_Y - tile.y;
-
-    assert!(digit_x < DIGIT_SIZE_X);
-    assert!(digit_y < DIGIT_SIZE_Y);
-
-    let Some(current_char) = digits.chars().nth(digit_index) else {
-        println!("Invalid digit idx: {}", digit_index);
-        return false;
-    };
-    let Some(current_pattern_block) = current_char.to_digit(10) else {
-        println!("Invalid digit index: {}", digit_index);
-        return false;
-    };
-    digit_patterns[current_pattern_block as usize * (DIGIT_SIZE_X * DIGIT_SIZE_Y)
-        + digit_x
-        + digit_y * DIGIT_SIZE_X]
-}
-
-fn update_clock_hands(mut query: Query<(&mut Transform, &ClockHand)>) {
+    let local_time = wall_clock.now(&time);
     for (mut transform, hand) in query.iter_mut() {
-        *transform = clock_hand_transform(*hand);
+        *transform = clock_hand_transform(*hand, local_time);
     }
 }
 
 fn clock_achievement_check(
-    start_time: Res<StartupWallClockTime>,
+    wall_clock: Res<WallClock>,
+    time: Res<Time<Real>>,
     mut achievement_queue: ResMut<AchievementQueue>,
 ) {
     if achievement_queue.time_flies_achieved {
@@ -507,20 +732,23 @@ fn clock_achievement_check(
     }
 
     use chrono::prelude::*;
-    let local_time = Local::now();
-    if local_time.minute() != start_time.time.minute() {
-        achievement_queue.queue.push_back(AchievementToBeAdded {
-            text: "Time flies when you're having fun".to_string(),
-        });
+    let local_time = wall_clock.now(&time);
+    if local_time.minute() != wall_clock.start.minute() {
+        achievement_queue.unlock("Time flies when you're having fun");
         achievement_queue.time_flies_achieved = true;
     }
 }
 
-fn clock_hand_transform(hand: ClockHand) -> Transform {
+fn clock_hand_transform(hand: ClockHand, local_time: chrono::DateTime<chrono::Local>) -> Transform {
     use chrono::prelude::*;
-    let local_time = Local::now();
 
     match hand {
+        ClockHand::Second => {
+            let second_angle = local_time.second() as f32 / 60.0 * TAU;
+            Transform::from_rotation(Quat::from_rotation_y(-second_angle))
+                * Transform::from_scale(vec3(0.01, 1.0, CLOCK_SECOND_HAND_LENGTH))
+                    .with_translation(vec3(0.0, 0.05, -CLOCK_SECOND_HAND_LENGTH / 2.0 + 0.015))
+        }
         ClockHand::Minute => {
             let minute_angle = local_time.minute() as f32 / 59.0 * (TAU * 59.0 / 60.0);
             Transform::from_rotation(Quat::from_rotation_y(-minute_angle))
@@ -535,3 +763,40 @@ fn clock_hand_transform(hand: ClockHand) -> Transform {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use replay::PlayerAction;
+
+    #[test]
+    fn manual_toggles_score_the_combo_multiplier() {
+        let mut combo = Combo::default();
+        combo.count = 25;
+        let mut app = App::new();
+        app.insert_resource(Score { value: 0 })
+            .insert_resource(PlayerInput {
+                actions: vec![PlayerAction::SwitchPressed, PlayerAction::SwitchReleased],
+                ..default()
+            })
+            .insert_resource(combo)
+            .init_resource::<ComboSettings>()
+            .init_resource::<ClickAnalyzer>()
+            .init_resource::<AchievementQueue>()
+            .add_event::<LightToggled>()
+            .add_event::<ToggleLight>()
+            .add_systems(Update, light_switch_update);
+        app.world.spawn(DirectionalLight::default());
+        app.world.send_event(ToggleLight);
+        app.update();
+
+        // 3 for the manual toggle at a combo of 25, 1 for the automatic one
+        assert_eq!(app.world.resource::<Score>().value, 4);
+        let toggles = app.world.resource::<Events<LightToggled>>();
+        let manual = toggles
+            .iter_current_update_events()
+            .map(|toggle| toggle.manual)
+            .collect::<Vec<_>>();
+        assert_eq!(manual, [true, false]);
+    }
+}
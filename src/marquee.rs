@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::{
+    bitmap_font::BitmapFont,
+    localization::transliterate,
+    settings::Settings,
+    wall::{HorizontalAlign, ScoreDisplay, WallFont, WallText, TOP_RIGHT_DIGIT_Y, WALL_TILES_X},
+    WallClock,
+};
+
+/// Seconds a message stands still in the middle of the wall for every pass it would scroll, with
+/// reduced motion
+const MARQUEE_HOLD_DURATION: f32 = 3.0;
+
+/// Messages scrolling across the wall. The score display yields while one is shown.
+#[derive(Resource)]
+pub struct Marquee {
+    queue: VecDeque<String>,
+    /// Horizontal position of the first column of the current message, in tiles
+    offset: f32,
+    /// Remaining passes of the current message
+    passes_left: usize,
+    /// Set while the current message is held in place instead of scrolling
+    hold: Option<Timer>,
+    /// Tiles per second
+    pub speed: f32,
+    /// Top row of the band the messages scroll along
+    pub row: i32,
+    /// How often every message crosses the wall
+    pub passes: usize,
+    /// Announces the current time when it finishes, if set
+    pub clock_timer: Option<Timer>,
+}
+
+/// Marks the [`WallText`] showing the current [`Marquee`] message.
+#[derive(Component)]
+pub struct MarqueeText;
+
+impl Default for Marquee {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            offset: 0.0,
+            passes_left: 0,
+            hold: None,
+            speed: 20.0,
+            row: TOP_RIGHT_DIGIT_Y as i32,
+            passes: 1,
+            clock_timer: Some(Timer::from_seconds(15.0 * 60.0, TimerMode::Repeating)),
+        }
+    }
+}
+
+impl Marquee {
+    pub fn push(&mut self, message: impl Into<String>) {
+        self.queue.push_back(message.into());
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.passes_left > 0
+    }
+}
+
+pub fn setup_marquee(mut commands: Commands, wall_font: Res<WallFont>) {
+    commands.init_resource::<Marquee>();
+    commands
+        .spawn(WallText {
+            text: String::new(),
+            font: wall_font.font.clone(),
+            position: IVec2::ZERO,
+            horizontal_align: HorizontalAlign::Left,
+            visible: false,
+            roll: None,
+        })
+        .insert(MarqueeText);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn marquee_update(
+    time: Res<Time>,
+    real_time: Res<Time<Real>>,
+    settings: Res<Settings>,
+    wall_clock: Res<WallClock>,
+    fonts: Res<Assets<BitmapFont>>,
+    mut marquee: ResMut<Marquee>,
+    mut marquee_text: Query<&mut WallText, With<MarqueeText>>,
+    mut score_display: Query<&mut WallText, (With<ScoreDisplay>, Without<MarqueeText>)>,
+) {
+    let Ok(mut text) = marquee_text.get_single_mut() else {
+        return;
+    };
+    let Some(font) = fonts.get(&text.font) else {
+        return;
+    };
+
+    if let Some(timer) = &mut marquee.clock_timer {
+        if timer.tick(time.delta()).just_finished() {
+            let now = wall_clock.now(&real_time).format("%H:%M").to_string();
+            marquee.push(now);
+        }
+    }
+
+    if let Some(hold) = &mut marquee.hold {
+        if hold.tick(time.delta()).finished() {
+            marquee.hold = None;
+            marquee.passes_left = 0;
+        }
+    } else if marquee.is_active() {
+        marquee.offset -= marquee.speed * time.delta_seconds();
+        if marquee.offset + (font.text_width(&text.text) as f32) < 0.0 {
+            // Wrap around to the right edge of the wall
+            marquee.offset = WALL_TILES_X as f32;
+            marquee.passes_left -= 1;
+        }
+    }
+    if !marquee.is_active() {
+        if let Some(message) = marquee.queue.pop_front() {
+            text.text = transliterate(&message, |c| font.has_glyph(c));
+            marquee.offset = WALL_TILES_X as f32;
+            marquee.passes_left = marquee.passes;
+            marquee.hold = settings.reduced_motion.then(|| {
+                let duration = MARQUEE_HOLD_DURATION * marquee.passes as f32;
+                Timer::from_seconds(duration, TimerMode::Once)
+            });
+        }
+    }
+
+    let active = marquee.is_active();
+    let (position, horizontal_align) = if marquee.hold.is_some() {
+        let center = IVec2::new(WALL_TILES_X as i32 / 2, marquee.row);
+        (center, HorizontalAlign::Center)
+    } else {
+        let position = IVec2::new(marquee.offset.floor() as i32, marquee.row);
+        (position, HorizontalAlign::Left)
+    };
+    // Only touch the texts when they actually move, every change redraws the wall text
+    if text.visible != active {
+        text.visible = active;
+    }
+    if active && text.position != position {
+        text.position = position;
+    }
+    if text.horizontal_align != horizontal_align {
+        text.horizontal_align = horizontal_align;
+    }
+    for mut score_text in score_display.iter_mut() {
+        if score_text.visible == active {
+            score_text.visible = !active;
+        }
+    }
+}
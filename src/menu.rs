@@ -0,0 +1,187 @@
+use bevy::{app::AppExit, math::vec3, prelude::*, sprite::Anchor};
+
+use crate::{
+    achievements::{AchievementQueue, AchievementStyle},
+    localization::Localization,
+};
+
+/// Seconds the splash screen stays up unless a key is pressed
+const SPLASH_DURATION: f32 = 2.0;
+
+#[derive(States, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum GameState {
+    #[default]
+    Splash,
+    /// Picking a profile
+    MainMenu,
+    Playing,
+    Paused,
+    /// Unlocked achievements
+    Gallery,
+    Settings,
+}
+
+/// Panels centred on the screen by [`menu_panels_update`]
+#[derive(Component)]
+pub struct MenuPanel;
+
+#[derive(Component)]
+pub struct SplashPanel;
+
+#[derive(Component)]
+pub struct PausePanel;
+
+#[derive(Component)]
+pub struct GalleryPanel;
+
+pub fn setup_menu(mut commands: Commands, achievement_style: Res<AchievementStyle>) {
+    let text_style = TextStyle {
+        font_size: 14.0,
+        ..achievement_style.text_style.clone()
+    };
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section(
+                "AcaGameJam Winner 2023",
+                TextStyle {
+                    font_size: 32.0,
+                    ..achievement_style.text_style.clone()
+                },
+            ),
+            text_anchor: Anchor::Center,
+            ..default()
+        })
+        .insert((SplashPanel, MenuPanel));
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section("", text_style.clone()),
+            text_anchor: Anchor::Center,
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert((PausePanel, MenuPanel));
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section("", text_style),
+            text_anchor: Anchor::Center,
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert((GalleryPanel, MenuPanel));
+}
+
+pub fn show<T: Component>(mut query: Query<&mut Visibility, With<T>>) {
+    for mut visibility in query.iter_mut() {
+        *visibility = Visibility::Inherited;
+    }
+}
+
+pub fn hide<T: Component>(mut query: Query<&mut Visibility, With<T>>) {
+    for mut visibility in query.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+/// Game time stands still outside of [`GameState::Playing`], from startup on, which freezes
+/// every timer and animation, including the achievement cards. The
+/// [`WallClock`](crate::WallClock) follows the real time instead.
+pub fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+pub fn unpause_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+pub fn splash_update(
+    time: Res<Time<Real>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut elapsed: Local<f32>,
+) {
+    *elapsed += time.delta_seconds();
+    if *elapsed >= SPLASH_DURATION
+        || keyboard_input.get_just_pressed().next().is_some()
+        || mouse_input.get_just_pressed().next().is_some()
+    {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+/// Moves between the menus. Settings return to wherever they were opened from.
+pub fn menu_navigation_update(
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut exit: EventWriter<AppExit>,
+    mut settings_return: Local<Option<GameState>>,
+) {
+    let pressed = |key| keyboard_input.just_pressed(key);
+    let state = *state.get();
+    let next = match state {
+        GameState::Splash => None,
+        GameState::MainMenu | GameState::Playing | GameState::Paused if pressed(KeyCode::F10) => {
+            *settings_return = Some(state);
+            Some(GameState::Settings)
+        }
+        GameState::MainMenu => None,
+        GameState::Playing if pressed(KeyCode::Escape) => Some(GameState::Paused),
+        GameState::Paused if pressed(KeyCode::Escape) => Some(GameState::Playing),
+        GameState::Paused if pressed(KeyCode::G) => Some(GameState::Gallery),
+        GameState::Paused if pressed(KeyCode::Q) => {
+            exit.send(AppExit);
+            None
+        }
+        GameState::Gallery if pressed(KeyCode::Escape) || pressed(KeyCode::G) => {
+            Some(GameState::Paused)
+        }
+        GameState::Settings if pressed(KeyCode::Escape) || pressed(KeyCode::F10) => {
+            Some(settings_return.take().unwrap_or(GameState::Paused))
+        }
+        GameState::Playing | GameState::Paused | GameState::Gallery | GameState::Settings => None,
+    };
+    if let Some(next) = next {
+        next_state.set(next);
+    }
+}
+
+pub fn menu_panels_update(
+    localization: Res<Localization>,
+    query_ortho: Query<&OrthographicProjection>,
+    mut panels: Query<&mut Transform, With<MenuPanel>>,
+    mut pause_panel: Query<&mut Text, With<PausePanel>>,
+) {
+    if localization.is_changed() {
+        for mut text in pause_panel.iter_mut() {
+            text.sections[0].value = localization.get("menu.paused").to_string();
+        }
+    }
+    let Ok(ortho) = query_ortho.get_single() else {
+        return;
+    };
+    for mut transform in panels.iter_mut() {
+        transform.translation = vec3(ortho.area.center().x, ortho.area.center().y, 3.0);
+    }
+}
+
+pub fn gallery_update(
+    achievement_queue: Res<AchievementQueue>,
+    localization: Res<Localization>,
+    mut panel: Query<&mut Text, With<GalleryPanel>>,
+) {
+    let mut achievements = achievement_queue
+        .unlocked
+        .iter()
+        .map(|achievement| localization.achievement(achievement))
+        .collect::<Vec<_>>();
+    achievements.sort();
+    let mut lines = localization.format("menu.gallery", &[("count", &achievements.len())]);
+    lines += "\n\n";
+    for achievement in achievements {
+        lines += &format!("* {achievement}\n");
+    }
+    for mut text in panel.iter_mut() {
+        text.sections[0].value = lines.clone() + "\n" + localization.get("menu.back");
+    }
+}
@@ -0,0 +1,363 @@
+use bevy::{app::AppExit, math::vec3, prelude::*, sprite::Anchor, utils::HashMap};
+use std::fs;
+
+use crate::{
+    achievements::{AchievementQueue, AchievementStyle},
+    cli::Options,
+    economy::Economy,
+    localization::Localization,
+    menu::GameState,
+    stats::{format_duration, Stats},
+    storage::{data_dir, load_key_values, parse_value, save_key_values},
+    Score,
+};
+
+const PROFILES_DIR: &str = "profiles";
+const LEADERBOARD_FILE: &str = "leaderboard.txt";
+const LEADERBOARD_SIZE: usize = 10;
+const PROFILE_SAVE_INTERVAL: f32 = 10.0;
+/// Achievements are stored in a single value, separated by this
+const ACHIEVEMENT_SEPARATOR: char = '|';
+
+/// The player everything is currently recorded for.
+/// Inserted once a profile was picked at startup.
+#[derive(Resource)]
+pub struct ActiveProfile {
+    pub name: String,
+    /// Score when the profile was picked, to tell what this session added
+    pub start_score: u64,
+    /// Score on disk while `--score` overrides the session's, which is then not saved
+    pub kept_score: Option<u64>,
+}
+
+impl ActiveProfile {
+    fn file(&self, file_name: &str) -> String {
+        format!("{PROFILES_DIR}/{}/{file_name}", self.name)
+    }
+
+    fn save(
+        &self,
+        score: &Score,
+        achievements: &AchievementQueue,
+        stats: &Stats,
+        economy: &Economy,
+    ) {
+        let mut achievements = achievements.unlocked.iter().cloned().collect::<Vec<_>>();
+        achievements.sort();
+        save_key_values(
+            &self.file("profile.txt"),
+            [
+                ("score", self.kept_score.unwrap_or(score.value).to_string()),
+                (
+                    "achievements",
+                    achievements.join(&ACHIEVEMENT_SEPARATOR.to_string()),
+                ),
+            ],
+        );
+        stats.save(&self.file("stats.txt"));
+        economy.save(&self.file("economy.txt"));
+    }
+}
+
+/// Startup profile picker. Removed once a profile is active.
+#[derive(Resource, Default)]
+pub struct ProfileSelection {
+    profiles: Vec<String>,
+    /// Name of a new profile while it is being typed
+    new_name: Option<String>,
+}
+
+impl ProfileSelection {
+    /// Whether keys are going into the name of a new profile, rather than being shortcuts.
+    pub fn is_typing(&self) -> bool {
+        self.new_name.is_some()
+    }
+}
+
+#[derive(Component)]
+pub struct ProfilePanel;
+
+#[derive(Component)]
+pub struct LeaderboardPanel;
+
+#[derive(Resource)]
+pub struct ProfileSaveTimer(Timer);
+
+/// Best results across all local profiles.
+#[derive(Resource, Default)]
+pub struct Leaderboard {
+    /// Most points scored in a single session, best first
+    pub session_scores: Vec<(u64, String)>,
+    /// Fastest time to 100 toggles in a session in seconds, best first
+    pub times_to_100: Vec<(f64, String)>,
+}
+
+impl Leaderboard {
+    fn load() -> Self {
+        let values = load_key_values(LEADERBOARD_FILE);
+        Self {
+            session_scores: leaderboard_entries(&values, "session_score"),
+            times_to_100: leaderboard_entries(&values, "time_to_100"),
+        }
+    }
+
+    fn save(&self) {
+        let session_scores = self
+            .session_scores
+            .iter()
+            .enumerate()
+            .map(|(i, (score, name))| (format!("session_score.{i}"), format!("{score} {name}")));
+        let times_to_100 = self
+            .times_to_100
+            .iter()
+            .enumerate()
+            .map(|(i, (time, name))| (format!("time_to_100.{i}"), format!("{time} {name}")));
+        save_key_values(LEADERBOARD_FILE, session_scores.chain(times_to_100));
+    }
+
+    fn submit_session_score(&mut self, name: &str, score: u64) {
+        self.session_scores.push((score, name.to_string()));
+        self.session_scores
+            .sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        self.session_scores.truncate(LEADERBOARD_SIZE);
+    }
+
+    fn submit_time_to_100(&mut self, name: &str, time: f64) {
+        self.times_to_100.push((time, name.to_string()));
+        self.times_to_100.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.times_to_100.truncate(LEADERBOARD_SIZE);
+    }
+}
+
+/// Parses `<prefix>.<rank> = <value> <name>` entries.
+fn leaderboard_entries<T: std::str::FromStr>(
+    values: &HashMap<String, String>,
+    prefix: &str,
+) -> Vec<(T, String)> {
+    (0..LEADERBOARD_SIZE)
+        .filter_map(|i| values.get(&format!("{prefix}.{i}")))
+        .filter_map(|entry| {
+            let (value, name) = entry.split_once(' ')?;
+            Some((value.parse().ok()?, name.to_string()))
+        })
+        .collect()
+}
+
+fn list_profiles() -> Vec<String> {
+    let mut profiles = fs::read_dir(data_dir().join(PROFILES_DIR))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    profiles.sort();
+    profiles
+}
+
+pub fn setup_profiles(mut commands: Commands, achievement_style: Res<AchievementStyle>) {
+    commands.insert_resource(ProfileSelection {
+        profiles: list_profiles(),
+        new_name: None,
+    });
+    commands.insert_resource(Leaderboard::load());
+    commands.insert_resource(ProfileSaveTimer(Timer::from_seconds(
+        PROFILE_SAVE_INTERVAL,
+        TimerMode::Repeating,
+    )));
+
+    let text_style = TextStyle {
+        font_size: 14.0,
+        ..achievement_style.text_style.clone()
+    };
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section("", text_style.clone()),
+            text_anchor: Anchor::Center,
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(ProfilePanel);
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section("", text_style),
+            text_anchor: Anchor::Center,
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(LeaderboardPanel);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn profile_selection_update(
+    mut commands: Commands,
+    mut selection: ResMut<ProfileSelection>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut score: ResMut<Score>,
+    mut achievement_queue: ResMut<AchievementQueue>,
+    mut stats: ResMut<Stats>,
+    mut economy: ResMut<Economy>,
+    options: Res<Options>,
+    mut next_state: ResMut<NextState<GameState>>,
+    localization: Res<Localization>,
+    query_ortho: Query<&OrthographicProjection>,
+    mut panel: Query<(Entity, &mut Text, &mut Transform), With<ProfilePanel>>,
+) {
+    let mut selected = None;
+    if let Some(new_name) = &mut selection.new_name {
+        for character in characters.read() {
+            if character.char.is_alphanumeric() || character.char == ' ' {
+                new_name.push(character.char);
+            }
+        }
+        if keyboard_input.just_pressed(KeyCode::Back) {
+            new_name.pop();
+        }
+        if keyboard_input.just_pressed(KeyCode::Return) && !new_name.trim().is_empty() {
+            selected = Some(new_name.trim().to_string());
+        }
+    } else {
+        characters.clear();
+        let number_keys = [
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+            KeyCode::Key7,
+            KeyCode::Key8,
+            KeyCode::Key9,
+        ];
+        for (key, profile) in number_keys.iter().zip(&selection.profiles) {
+            if keyboard_input.just_pressed(*key) {
+                selected = Some(profile.clone());
+            }
+        }
+        if keyboard_input.just_pressed(KeyCode::N) {
+            selection.new_name = Some(String::new());
+        }
+    }
+
+    let Ok((panel_entity, mut text, mut transform)) = panel.get_single_mut() else {
+        return;
+    };
+
+    if let Some(name) = selected {
+        let mut profile = ActiveProfile {
+            name,
+            start_score: 0,
+            kept_score: None,
+        };
+        let values = load_key_values(&profile.file("profile.txt"));
+        let saved_score = parse_value(&values, "score").unwrap_or_default();
+        if options.score.is_some() {
+            profile.kept_score = Some(saved_score);
+        }
+        score.value = options.score.unwrap_or(saved_score);
+        profile.start_score = score.value;
+        achievement_queue.unlocked = values
+            .get("achievements")
+            .map(|achievements| {
+                achievements
+                    .split(ACHIEVEMENT_SEPARATOR)
+                    .filter(|achievement| !achievement.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        *stats = Stats::load(&profile.file("stats.txt"));
+        stats.sessions_played += 1;
+        *economy = Economy::load(&profile.file("economy.txt"));
+
+        info!("Playing as {}", profile.name);
+        commands.insert_resource(profile);
+        commands.remove_resource::<ProfileSelection>();
+        next_state.set(GameState::Playing);
+        commands.entity(panel_entity).despawn_recursive();
+        return;
+    }
+
+    if let Ok(ortho) = query_ortho.get_single() {
+        transform.translation = vec3(ortho.area.center().x, ortho.area.center().y, 2.0);
+    }
+    text.sections[0].value = match &selection.new_name {
+        Some(new_name) => localization.format("profile.name", &[("name", new_name)]),
+        None => {
+            let mut list = format!("{}\n\n", localization.get("profile.select"));
+            for (i, profile) in selection.profiles.iter().take(9).enumerate() {
+                list += &format!("[{}] {profile}\n", i + 1);
+            }
+            list + localization.get("profile.new")
+        }
+    };
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn profile_save(
+    profile: Res<ActiveProfile>,
+    score: Res<Score>,
+    achievement_queue: Res<AchievementQueue>,
+    stats: Res<Stats>,
+    economy: Res<Economy>,
+    mut leaderboard: ResMut<Leaderboard>,
+    time: Res<Time>,
+    mut timer: ResMut<ProfileSaveTimer>,
+    exit: EventReader<AppExit>,
+    mut submitted_time_to_100: Local<bool>,
+) {
+    if let (Some(time_to_100), false) = (stats.time_to_100, *submitted_time_to_100) {
+        *submitted_time_to_100 = true;
+        leaderboard.submit_time_to_100(&profile.name, time_to_100);
+        leaderboard.save();
+    }
+
+    let exiting = !exit.is_empty();
+    if timer.0.tick(time.delta()).just_finished() || exiting {
+        profile.save(&score, &achievement_queue, &stats, &economy);
+    }
+    let session_score = score.value.saturating_sub(profile.start_score);
+    if exiting && session_score > 0 {
+        leaderboard.submit_session_score(&profile.name, session_score);
+        leaderboard.save();
+    }
+}
+
+pub fn leaderboard_panel_update(
+    leaderboard: Res<Leaderboard>,
+    keyboard_input: Res<Input<KeyCode>>,
+    selection: Option<Res<ProfileSelection>>,
+    localization: Res<Localization>,
+    query_ortho: Query<&OrthographicProjection>,
+    mut panel: Query<(&mut Text, &mut Transform, &mut Visibility), With<LeaderboardPanel>>,
+) {
+    let Ok((mut text, mut transform, mut visibility)) = panel.get_single_mut() else {
+        return;
+    };
+    let typing = selection.is_some_and(|selection| selection.is_typing());
+    if keyboard_input.just_pressed(KeyCode::L) && !typing {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+    if *visibility == Visibility::Hidden {
+        return;
+    }
+    if let Ok(ortho) = query_ortho.get_single() {
+        transform.translation = vec3(ortho.area.center().x, ortho.area.center().y, 2.0);
+    }
+
+    let mut board = format!("{}\n\n", localization.get("leaderboard.sessions"));
+    for (i, (score, name)) in leaderboard.session_scores.iter().enumerate() {
+        board += &format!("{:>2}. {score:>8} {name}\n", i + 1);
+    }
+    board += &format!("\n{}\n\n", localization.get("leaderboard.fastest"));
+    for (i, (time, name)) in leaderboard.times_to_100.iter().enumerate() {
+        board += &format!("{:>2}. {:>8} {name}\n", i + 1, format_duration(*time));
+    }
+    text.sections[0].value = board;
+}
@@ -0,0 +1,688 @@
+use bevy::{
+    app::AppExit,
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    time::TimeUpdateStrategy,
+    utils::HashMap,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{fmt, fs, path::Path, time::Duration};
+
+use crate::{
+    achievements::AchievementQueue,
+    camera::zoom_modifier_pressed,
+    cli::Options,
+    economy::Economy,
+    menu::GameState,
+    profiles::{ActiveProfile, ProfilePanel, ProfileSelection},
+    settings::Settings,
+    storage::data_dir,
+    wall::WallTilePalette,
+    Score, WallClock,
+};
+
+/// Randomness that gameplay depends on. Reseeded when a recording or playback starts, so that
+/// playback draws the same values as the recording.
+#[derive(Resource)]
+pub struct GameRng(pub StdRng);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self(StdRng::from_entropy())
+    }
+}
+
+/// Every session is recorded to this file in the data directory
+const LAST_REPLAY_FILE: &str = "replays/last.replay";
+/// Longest frame during playback
+const PLAYBACK_FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// Keys that gameplay reads from the [`PlayerInput`]: the shop, challenges and color matching
+const GAMEPLAY_KEYS: [KeyCode; 12] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::Back,
+    KeyCode::Return,
+    KeyCode::Space,
+];
+
+/// Something the player did, live or in a replay.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlayerAction {
+    SwitchPressed,
+    SwitchReleased,
+    /// Dimmer movement in scroll lines
+    Dimmer(f32),
+    /// One of the [`GAMEPLAY_KEYS`] went down
+    Key(KeyCode),
+    /// [`Settings::scroll_sensitivity`] changed
+    ScrollSensitivity(f32),
+    /// [`Settings::exclude_suspicious_clicks`] changed
+    ExcludeSuspiciousClicks(bool),
+}
+
+/// The player's actions in the current frame. Gameplay reads these instead of the raw input,
+/// so that a [`ReplayPlayback`] can stand in for the player.
+#[derive(Resource)]
+pub struct PlayerInput {
+    pub actions: Vec<PlayerAction>,
+    /// Whether the switch is held down
+    pub switch_held: bool,
+    /// The settings gameplay depends on, as recorded rather than as currently set
+    pub scroll_sensitivity: f32,
+    pub exclude_suspicious_clicks: bool,
+}
+
+impl Default for PlayerInput {
+    fn default() -> Self {
+        let settings = Settings::default();
+        Self {
+            actions: Vec::new(),
+            switch_held: false,
+            scroll_sensitivity: settings.scroll_sensitivity,
+            exclude_suspicious_clicks: settings.exclude_suspicious_clicks,
+        }
+    }
+}
+
+impl PlayerInput {
+    /// Times the switch was flipped this frame.
+    pub fn switch_releases(&self) -> usize {
+        self.actions
+            .iter()
+            .filter(|&&action| action == PlayerAction::SwitchReleased)
+            .count()
+    }
+
+    /// Whether a gameplay key went down this frame.
+    pub fn just_pressed(&self, key: KeyCode) -> bool {
+        self.actions.contains(&PlayerAction::Key(key))
+    }
+
+    pub fn dimmer_moves(&self) -> impl Iterator<Item = f32> + '_ {
+        self.actions.iter().filter_map(|action| match action {
+            PlayerAction::Dimmer(amount) => Some(*amount),
+            _ => None,
+        })
+    }
+}
+
+pub struct ReplayEvent {
+    /// Since the start of the recording
+    pub time: Duration,
+    pub action: PlayerAction,
+}
+
+/// A recorded session: the state it started from and every action of the player.
+///
+/// ```text
+/// seed 1234
+/// rng_seed 5678
+/// start_time 2023-11-25T14:03:00+01:00
+/// profile Anke
+/// score 42
+/// achievement Lights on
+/// economy lumens 12.5
+/// economy level.auto_toggler 1
+/// 0 scroll_sensitivity 1.25
+/// 1500000000 press
+/// 1600000000 release
+/// 2000000000 dimmer -1
+/// 2500000000 key F1
+/// ```
+///
+/// Event lines start with their time in nanoseconds.
+pub struct Replay {
+    /// [`WallTilePalette::seed`]
+    pub seed: u64,
+    /// [`GameRng`] seed
+    pub rng_seed: u64,
+    /// Wall clock time when the recording started
+    pub start_time: chrono::DateTime<chrono::FixedOffset>,
+    pub profile: String,
+    pub score: u64,
+    pub achievements: Vec<String>,
+    /// Lumens and upgrades when the recording started
+    pub economy: Economy,
+    pub events: Vec<ReplayEvent>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "could not read replay: {err}"),
+            ReplayError::Parse { line, message } => {
+                write!(f, "invalid replay (line {line}): {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(err: std::io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ReplayError> {
+        let mut seed = None;
+        let mut rng_seed = None;
+        let mut start_time = None;
+        let mut profile = String::new();
+        let mut score = 0;
+        let mut achievements = Vec::new();
+        let mut economy = HashMap::new();
+        let mut events = Vec::new();
+
+        for (line_number, line) in source.lines().enumerate() {
+            let error = |message: &str| ReplayError::Parse {
+                line: line_number + 1,
+                message: message.to_string(),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "seed" => seed = Some(value.parse().map_err(|_| error("invalid seed"))?),
+                "rng_seed" => {
+                    rng_seed = Some(value.parse().map_err(|_| error("invalid rng seed"))?);
+                }
+                "start_time" => {
+                    start_time = Some(
+                        chrono::DateTime::parse_from_rfc3339(value)
+                            .map_err(|_| error("invalid start time"))?,
+                    );
+                }
+                "profile" => profile = value.to_string(),
+                "score" => score = value.parse().map_err(|_| error("invalid score"))?,
+                "achievement" => achievements.push(value.to_string()),
+                "economy" => {
+                    let (key, value) = value
+                        .split_once(' ')
+                        .ok_or_else(|| error("invalid economy value"))?;
+                    economy.insert(key.to_string(), value.to_string());
+                }
+                time => {
+                    let time = time
+                        .parse()
+                        .map(Duration::from_nanos)
+                        .map_err(|_| error("unknown statement"))?;
+                    let mut words = value.split_whitespace();
+                    let action = match (words.next(), words.next()) {
+                        (Some("press"), None) => PlayerAction::SwitchPressed,
+                        (Some("release"), None) => PlayerAction::SwitchReleased,
+                        (Some("dimmer"), Some(amount)) => PlayerAction::Dimmer(
+                            amount.parse().map_err(|_| error("invalid dimmer amount"))?,
+                        ),
+                        (Some("key"), Some(name)) => PlayerAction::Key(
+                            GAMEPLAY_KEYS
+                                .into_iter()
+                                .find(|key| format!("{key:?}") == name)
+                                .ok_or_else(|| error("unknown key"))?,
+                        ),
+                        (Some("scroll_sensitivity"), Some(sensitivity)) => {
+                            PlayerAction::ScrollSensitivity(
+                                sensitivity
+                                    .parse()
+                                    .map_err(|_| error("invalid scroll sensitivity"))?,
+                            )
+                        }
+                        (Some("exclude_suspicious_clicks"), Some(exclude)) => {
+                            PlayerAction::ExcludeSuspiciousClicks(
+                                exclude
+                                    .parse()
+                                    .map_err(|_| error("expected true or false"))?,
+                            )
+                        }
+                        _ => return Err(error("unknown action")),
+                    };
+                    if events
+                        .last()
+                        .is_some_and(|last: &ReplayEvent| last.time > time)
+                    {
+                        return Err(error("events must be in chronological order"));
+                    }
+                    events.push(ReplayEvent { time, action });
+                }
+            }
+        }
+
+        Ok(Self {
+            seed: seed.ok_or_else(|| ReplayError::Parse {
+                line: 0,
+                message: "missing seed".to_string(),
+            })?,
+            rng_seed: rng_seed.ok_or_else(|| ReplayError::Parse {
+                line: 0,
+                message: "missing rng seed".to_string(),
+            })?,
+            start_time: start_time.ok_or_else(|| ReplayError::Parse {
+                line: 0,
+                message: "missing start time".to_string(),
+            })?,
+            profile,
+            score,
+            achievements,
+            economy: Economy::from_values(&economy),
+            events,
+        })
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "rng_seed {}", self.rng_seed)?;
+        writeln!(f, "start_time {}", self.start_time.to_rfc3339())?;
+        writeln!(f, "profile {}", self.profile)?;
+        writeln!(f, "score {}", self.score)?;
+        for achievement in &self.achievements {
+            writeln!(f, "achievement {achievement}")?;
+        }
+        for (key, value) in self.economy.to_values() {
+            writeln!(f, "economy {key} {value}")?;
+        }
+        for event in &self.events {
+            let time = event.time.as_nanos();
+            match event.action {
+                PlayerAction::SwitchPressed => writeln!(f, "{time} press")?,
+                PlayerAction::SwitchReleased => writeln!(f, "{time} release")?,
+                PlayerAction::Dimmer(amount) => writeln!(f, "{time} dimmer {amount}")?,
+                PlayerAction::Key(key) => writeln!(f, "{time} key {key:?}")?,
+                PlayerAction::ScrollSensitivity(sensitivity) => {
+                    writeln!(f, "{time} scroll_sensitivity {sensitivity}")?;
+                }
+                PlayerAction::ExcludeSuspiciousClicks(exclude) => {
+                    writeln!(f, "{time} exclude_suspicious_clicks {exclude}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Records the session once a profile is active.
+#[derive(Resource, Default)]
+pub struct ReplayRecorder {
+    replay: Option<Replay>,
+    /// Game time when the recording started
+    start: Duration,
+}
+
+/// Plays a [`Replay`] back instead of taking input from the player.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    next_event: usize,
+    /// Game time when the playback started, once it has
+    start: Option<Duration>,
+    /// Quit once all events were played back
+    pub exit_when_finished: bool,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            next_event: 0,
+            start: None,
+            exit_when_finished: false,
+        }
+    }
+
+    /// Game time at which the next event is due.
+    fn next_event_time(&self) -> Option<Duration> {
+        let event = self.replay.events.get(self.next_event)?;
+        Some(self.start? + event.time)
+    }
+}
+
+pub fn setup_replay(mut commands: Commands, options: Res<Options>) {
+    commands.init_resource::<PlayerInput>();
+    commands.init_resource::<GameRng>();
+    let Some(path) = &options.replay else {
+        commands.init_resource::<ReplayRecorder>();
+        return;
+    };
+    match Replay::load(path) {
+        Ok(replay) => {
+            info!("Playing back {path:?}");
+            commands.insert_resource(ReplayPlayback {
+                exit_when_finished: options.headless,
+                ..ReplayPlayback::new(replay)
+            });
+            commands.insert_resource(TimeUpdateStrategy::ManualDuration(PLAYBACK_FRAME_TIME));
+        }
+        Err(err) => {
+            error!("{err}");
+            commands.init_resource::<ReplayRecorder>();
+        }
+    }
+}
+
+/// Reads this frame's [`PlayerInput`] from the mouse and keyboard, or from the replay being played back.
+#[allow(clippy::too_many_arguments)]
+pub fn player_input_update(
+    mut input: ResMut<PlayerInput>,
+    mouse_input: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut scroll_events: EventReader<MouseWheel>,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    state: Res<State<GameState>>,
+    playback: Option<ResMut<ReplayPlayback>>,
+) {
+    input.actions.clear();
+    if *state.get() != GameState::Playing {
+        // Menu clicks are neither gameplay nor recorded
+        scroll_events.clear();
+    } else if let Some(mut playback) = playback {
+        scroll_events.clear();
+        while playback
+            .next_event_time()
+            .is_some_and(|event_time| event_time <= time.elapsed())
+        {
+            let action = playback.replay.events[playback.next_event].action;
+            input.actions.push(action);
+            playback.next_event += 1;
+        }
+    } else {
+        if mouse_input.just_pressed(MouseButton::Middle) {
+            input.actions.push(PlayerAction::SwitchPressed);
+        }
+        if mouse_input.just_released(MouseButton::Middle) {
+            input.actions.push(PlayerAction::SwitchReleased);
+        }
+        if zoom_modifier_pressed(&keyboard_input) {
+            // The camera zooms instead
+            scroll_events.clear();
+        }
+        for event in scroll_events.read() {
+            let amount = match event.unit {
+                MouseScrollUnit::Line => event.y,
+                MouseScrollUnit::Pixel => event.y * 10.0,
+            };
+            // Recorded with the sensitivity applied, so replays don't depend on the settings
+            input
+                .actions
+                .push(PlayerAction::Dimmer(amount * settings.scroll_sensitivity));
+        }
+        for key in GAMEPLAY_KEYS {
+            if keyboard_input.just_pressed(key) {
+                input.actions.push(PlayerAction::Key(key));
+            }
+        }
+        // Changed in the settings menu, and recorded so playback doesn't depend on the settings
+        if settings.scroll_sensitivity != input.scroll_sensitivity {
+            let sensitivity = settings.scroll_sensitivity;
+            input
+                .actions
+                .push(PlayerAction::ScrollSensitivity(sensitivity));
+        }
+        if settings.exclude_suspicious_clicks != input.exclude_suspicious_clicks {
+            let exclude = settings.exclude_suspicious_clicks;
+            input
+                .actions
+                .push(PlayerAction::ExcludeSuspiciousClicks(exclude));
+        }
+    }
+
+    for i in 0..input.actions.len() {
+        match input.actions[i] {
+            PlayerAction::SwitchPressed => input.switch_held = true,
+            PlayerAction::SwitchReleased => input.switch_held = false,
+            PlayerAction::ScrollSensitivity(sensitivity) => input.scroll_sensitivity = sensitivity,
+            PlayerAction::ExcludeSuspiciousClicks(exclude) => {
+                input.exclude_suspicious_clicks = exclude;
+            }
+            PlayerAction::Dimmer(_) | PlayerAction::Key(_) => {}
+        }
+    }
+}
+
+/// Applies the starting state of the replay and takes over from the profile picker.
+#[allow(clippy::too_many_arguments)]
+pub fn replay_playback_start(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    time: Res<Time>,
+    real_time: Res<Time<Real>>,
+    mut score: ResMut<Score>,
+    mut achievement_queue: ResMut<AchievementQueue>,
+    mut palette: ResMut<WallTilePalette>,
+    mut wall_clock: ResMut<WallClock>,
+    mut rng: ResMut<GameRng>,
+    mut economy: ResMut<Economy>,
+    mut next_state: ResMut<NextState<GameState>>,
+    profile_panel: Query<Entity, With<ProfilePanel>>,
+) {
+    if playback.start.is_some() {
+        return;
+    }
+    playback.start = Some(time.elapsed());
+
+    let replay = &playback.replay;
+    score.value = replay.score;
+    achievement_queue.unlocked = replay.achievements.iter().cloned().collect();
+    palette.seed = replay.seed;
+    *economy = replay.economy.clone();
+    rng.0 = StdRng::seed_from_u64(replay.rng_seed);
+    wall_clock.start = replay.start_time.with_timezone(&chrono::Local)
+        - chrono::Duration::from_std(real_time.elapsed())
+            .unwrap_or_else(|_| chrono::Duration::zero());
+
+    commands.insert_resource(ActiveProfile {
+        name: replay.profile.clone(),
+        start_score: replay.score,
+        kept_score: None,
+    });
+    commands.remove_resource::<ProfileSelection>();
+    next_state.set(GameState::Playing);
+    for entity in profile_panel.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Advances the game time exactly to the next event, so it sees the same timestamps as in the
+/// recording.
+pub fn replay_playback_time(
+    playback: Res<ReplayPlayback>,
+    time: Res<Time>,
+    mut time_update_strategy: ResMut<TimeUpdateStrategy>,
+    score: Res<Score>,
+    mut exit: EventWriter<AppExit>,
+    mut finished: Local<bool>,
+) {
+    let frame_time = match playback.next_event_time() {
+        Some(event_time) => event_time
+            .saturating_sub(time.elapsed())
+            .min(PLAYBACK_FRAME_TIME),
+        None => PLAYBACK_FRAME_TIME,
+    };
+    *time_update_strategy = TimeUpdateStrategy::ManualDuration(frame_time);
+
+    if playback.start.is_some() && playback.next_event >= playback.replay.events.len() && !*finished
+    {
+        *finished = true;
+        info!("Replay finished with a score of {}", score.value);
+        if playback.exit_when_finished {
+            exit.send(AppExit);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn replay_record(
+    mut recorder: ResMut<ReplayRecorder>,
+    input: Res<PlayerInput>,
+    time: Res<Time>,
+    profile: Res<ActiveProfile>,
+    score: Res<Score>,
+    achievement_queue: Res<AchievementQueue>,
+    palette: Res<WallTilePalette>,
+    wall_clock: Res<WallClock>,
+    real_time: Res<Time<Real>>,
+    mut rng: ResMut<GameRng>,
+    economy: Res<Economy>,
+) {
+    if recorder.replay.is_none() {
+        let mut achievements = achievement_queue
+            .unlocked
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        achievements.sort();
+        let rng_seed = rand::random();
+        rng.0 = StdRng::seed_from_u64(rng_seed);
+        recorder.start = time.elapsed();
+        recorder.replay = Some(Replay {
+            seed: palette.seed,
+            rng_seed,
+            start_time: wall_clock.now(&real_time).fixed_offset(),
+            profile: profile.name.clone(),
+            score: score.value,
+            achievements,
+            economy: economy.clone(),
+            events: Vec::new(),
+        });
+    }
+
+    let event_time = time.elapsed().saturating_sub(recorder.start);
+    if let Some(replay) = &mut recorder.replay {
+        replay
+            .events
+            .extend(input.actions.iter().map(|&action| ReplayEvent {
+                time: event_time,
+                action,
+            }));
+    }
+}
+
+pub fn replay_save(recorder: Res<ReplayRecorder>, exit: EventReader<AppExit>) {
+    if exit.is_empty() {
+        return;
+    }
+    let Some(replay) = &recorder.replay else {
+        return;
+    };
+    let path = data_dir().join(LAST_REPLAY_FILE);
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, replay.to_string()));
+    match result {
+        Ok(()) => info!("Saved replay to {path:?}"),
+        Err(err) => warn!("Could not write {path:?}: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::economy::Upgrade;
+
+    const REPLAY: &str = "\
+seed 1234
+rng_seed 5678
+start_time 2023-11-25T14:03:00+01:00
+profile Anke
+score 42
+achievement Lights on
+economy lumens 12.5
+economy level.auto_toggler 2
+# Comments and empty lines are skipped
+
+0 scroll_sensitivity 1.25
+1500000000 press
+1600000000 release
+2000000000 dimmer -1.5
+2000000000 key F1
+2100000000 exclude_suspicious_clicks true
+";
+
+    fn error_line(source: &str) -> Option<usize> {
+        match Replay::parse(source) {
+            Err(ReplayError::Parse { line, .. }) => Some(line),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_header_and_events() {
+        let replay = Replay::parse(REPLAY).unwrap();
+        assert_eq!((replay.seed, replay.rng_seed), (1234, 5678));
+        assert_eq!(replay.start_time.timestamp(), 1_700_917_380);
+        assert_eq!(replay.profile, "Anke");
+        assert_eq!(replay.score, 42);
+        assert_eq!(replay.achievements, ["Lights on"]);
+        assert_eq!(replay.economy.lumens, 12.5);
+        assert_eq!(replay.economy.level(Upgrade::AutoToggler), 2);
+        assert_eq!(replay.economy.level(Upgrade::MultiClick), 0);
+        let events = replay
+            .events
+            .iter()
+            .map(|event| (event.time.as_millis(), event.action))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                (0, PlayerAction::ScrollSensitivity(1.25)),
+                (1500, PlayerAction::SwitchPressed),
+                (1600, PlayerAction::SwitchReleased),
+                (2000, PlayerAction::Dimmer(-1.5)),
+                (2000, PlayerAction::Key(KeyCode::F1)),
+                (2100, PlayerAction::ExcludeSuspiciousClicks(true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn saved_replays_parse_the_same() {
+        let saved = Replay::parse(REPLAY).unwrap().to_string();
+        assert_eq!(Replay::parse(&saved).unwrap().to_string(), saved);
+        assert!(saved.contains("2000000000 key F1\n"));
+    }
+
+    #[test]
+    fn rejects_invalid_replays() {
+        assert_eq!(
+            error_line("rng_seed 1\nstart_time 2023-11-25T14:03:00Z\n"),
+            Some(0)
+        );
+        assert_eq!(
+            error_line("seed 1\nstart_time 2023-11-25T14:03:00Z\n"),
+            Some(0)
+        );
+        assert_eq!(error_line("seed -1\n"), Some(1));
+        assert_eq!(error_line("seed 1\nstart_time yesterday\n"), Some(2));
+        assert_eq!(error_line("seed 1\n100 jump\n"), Some(2));
+        assert_eq!(error_line("seed 1\n100 key Escape\n"), Some(2));
+        assert_eq!(
+            error_line("seed 1\n100 exclude_suspicious_clicks yes\n"),
+            Some(2)
+        );
+        assert_eq!(error_line("seed 1\neconomy lumens\n"), Some(2));
+        assert_eq!(error_line("seed 1\n200 press\n100 release\n"), Some(3));
+    }
+}
@@ -0,0 +1,568 @@
+use bevy::{
+    math::vec3,
+    prelude::*,
+    render::camera::ScalingMode,
+    sprite::Anchor,
+    window::{PresentMode, PrimaryWindow, WindowMode},
+};
+
+use crate::{
+    achievements::AchievementStyle,
+    cli::Options,
+    localization::{Localization, LANGUAGES},
+    storage::{load_key_values, parse_value, save_key_values},
+    wall::{WallTilePalette, PALETTES},
+    wall_animation::WallAnimationSettings,
+};
+
+const SETTINGS_FILE: &str = "settings.txt";
+
+/// Corner of the screen the achievement cards stack up in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ToastPosition {
+    #[default]
+    BottomRight,
+    BottomLeft,
+    TopRight,
+    TopLeft,
+}
+
+impl ToastPosition {
+    const ALL: [ToastPosition; 4] = [
+        ToastPosition::BottomRight,
+        ToastPosition::BottomLeft,
+        ToastPosition::TopRight,
+        ToastPosition::TopLeft,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            ToastPosition::BottomRight => "bottom_right",
+            ToastPosition::BottomLeft => "bottom_left",
+            ToastPosition::TopRight => "top_right",
+            ToastPosition::TopLeft => "top_left",
+        }
+    }
+
+    pub fn is_top(self) -> bool {
+        matches!(self, ToastPosition::TopRight | ToastPosition::TopLeft)
+    }
+
+    pub fn is_left(self) -> bool {
+        matches!(self, ToastPosition::BottomLeft | ToastPosition::TopLeft)
+    }
+}
+
+/// How the 2D overlay with the achievement cards and menus scales with the window.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum UiScaling {
+    /// Constant size in logical pixels, so it follows the display's DPI scale factor
+    #[default]
+    Dpi,
+    /// Grows and shrinks with the window, as if it always was [`UI_REFERENCE_SIZE`]
+    Window,
+}
+
+/// Window size the [`UiScaling::Window`] layout is made for, Bevy's default window size
+const UI_REFERENCE_SIZE: Vec2 = Vec2::new(1280.0, 720.0);
+
+impl UiScaling {
+    const ALL: [UiScaling; 2] = [UiScaling::Dpi, UiScaling::Window];
+
+    fn key(self) -> &'static str {
+        match self {
+            UiScaling::Dpi => "dpi",
+            UiScaling::Window => "window",
+        }
+    }
+
+    pub fn scaling_mode(self) -> ScalingMode {
+        match self {
+            UiScaling::Dpi => ScalingMode::WindowSize(1.0),
+            UiScaling::Window => ScalingMode::AutoMin {
+                min_width: UI_REFERENCE_SIZE.x,
+                min_height: UI_REFERENCE_SIZE.y,
+            },
+        }
+    }
+}
+
+/// Player preferences, read from the settings file at startup and written back on every change.
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct Settings {
+    /// Master volume, between 0 and 1
+    pub volume: f32,
+    /// Volumes of the [`AudioCategory`](crate::audio::AudioCategory) mixer channels
+    pub sfx_volume: f32,
+    pub ambience_volume: f32,
+    pub music_volume: f32,
+    /// Ticking and chiming of the wall clock
+    pub clock_sound: bool,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// Multiplies the dimmer movement per scroll step
+    pub scroll_sensitivity: f32,
+    pub toast_position: ToastPosition,
+    /// One of [`PALETTES`]
+    pub palette: String,
+    /// Turns off the wall animations, the scrolling marquee, sliding achievement cards and
+    /// particle effects
+    pub reduced_motion: bool,
+    /// Size of the achievement cards and their text
+    pub toast_scale: f32,
+    pub ui_scaling: UiScaling,
+    /// Writes an [`Announcement`](crate::announcements::Announcement) for every unlocked
+    /// achievement and the settled score
+    pub announcements: bool,
+    /// Leaves clicks the [`ClickAnalyzer`](crate::click_analyzer::ClickAnalyzer) finds suspicious
+    /// out of the score and statistics
+    pub exclude_suspicious_clicks: bool,
+    /// One of [`LANGUAGES`]
+    pub language: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            sfx_volume: 1.0,
+            ambience_volume: 1.0,
+            music_volume: 0.5,
+            clock_sound: true,
+            fullscreen: false,
+            vsync: true,
+            scroll_sensitivity: 1.0,
+            toast_position: ToastPosition::default(),
+            palette: PALETTES[0].0.to_string(),
+            reduced_motion: false,
+            toast_scale: 1.0,
+            ui_scaling: UiScaling::default(),
+            announcements: false,
+            exclude_suspicious_clicks: false,
+            language: LANGUAGES[0].to_string(),
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file. Missing or invalid values keep their defaults.
+    pub fn load() -> Self {
+        let values = load_key_values(SETTINGS_FILE);
+        let default = Self::default();
+        let known = |value: Option<&String>, names: &[&str], default: String| {
+            value
+                .filter(|value| names.contains(&value.as_str()))
+                .cloned()
+                .unwrap_or(default)
+        };
+        let volume = |key, default| {
+            parse_value::<f32>(&values, key).map_or(default, |volume| volume.clamp(0.0, 1.0))
+        };
+        let palettes = PALETTES.map(|(name, _, _)| name);
+        Self {
+            volume: volume("volume", default.volume),
+            sfx_volume: volume("sfx_volume", default.sfx_volume),
+            ambience_volume: volume("ambience_volume", default.ambience_volume),
+            music_volume: volume("music_volume", default.music_volume),
+            clock_sound: parse_value(&values, "clock_sound").unwrap_or(default.clock_sound),
+            fullscreen: parse_value(&values, "fullscreen").unwrap_or(default.fullscreen),
+            vsync: parse_value(&values, "vsync").unwrap_or(default.vsync),
+            scroll_sensitivity: parse_value::<f32>(&values, "scroll_sensitivity")
+                .filter(|sensitivity| *sensitivity > 0.0)
+                .unwrap_or(default.scroll_sensitivity),
+            toast_position: values
+                .get("toast_position")
+                .and_then(|key| {
+                    ToastPosition::ALL
+                        .into_iter()
+                        .find(|position| position.key() == key)
+                })
+                .unwrap_or(default.toast_position),
+            palette: known(values.get("palette"), &palettes, default.palette),
+            reduced_motion: parse_value(&values, "reduced_motion")
+                .unwrap_or(default.reduced_motion),
+            toast_scale: parse_value::<f32>(&values, "toast_scale")
+                .map_or(default.toast_scale, |scale| scale.clamp(0.5, 2.0)),
+            ui_scaling: values
+                .get("ui_scaling")
+                .and_then(|key| {
+                    UiScaling::ALL
+                        .into_iter()
+                        .find(|scaling| scaling.key() == key)
+                })
+                .unwrap_or(default.ui_scaling),
+            announcements: parse_value(&values, "announcements").unwrap_or(default.announcements),
+            exclude_suspicious_clicks: parse_value(&values, "exclude_suspicious_clicks")
+                .unwrap_or(default.exclude_suspicious_clicks),
+            language: known(values.get("language"), &LANGUAGES, default.language),
+        }
+    }
+
+    fn save(&self) {
+        save_key_values(
+            SETTINGS_FILE,
+            [
+                ("volume", self.volume.to_string()),
+                ("sfx_volume", self.sfx_volume.to_string()),
+                ("ambience_volume", self.ambience_volume.to_string()),
+                ("music_volume", self.music_volume.to_string()),
+                ("clock_sound", self.clock_sound.to_string()),
+                ("fullscreen", self.fullscreen.to_string()),
+                ("vsync", self.vsync.to_string()),
+                ("scroll_sensitivity", self.scroll_sensitivity.to_string()),
+                ("toast_position", self.toast_position.key().to_string()),
+                ("palette", self.palette.clone()),
+                ("reduced_motion", self.reduced_motion.to_string()),
+                ("toast_scale", self.toast_scale.to_string()),
+                ("ui_scaling", self.ui_scaling.key().to_string()),
+                ("announcements", self.announcements.to_string()),
+                (
+                    "exclude_suspicious_clicks",
+                    self.exclude_suspicious_clicks.to_string(),
+                ),
+                ("language", self.language.clone()),
+            ],
+        );
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        if self.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        }
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        if self.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        }
+    }
+}
+
+/// Settings given on the command line. They win over the [`Settings`] for this run, but are never
+/// written to the settings file. Changing an overridden setting in the menu makes it the player's
+/// own again.
+#[derive(Resource, Default, Debug)]
+pub struct SettingsOverrides {
+    pub palette: Option<String>,
+    pub fullscreen: Option<bool>,
+    pub vsync: Option<bool>,
+}
+
+impl SettingsOverrides {
+    pub fn new(options: &Options) -> Self {
+        Self {
+            palette: options.palette.clone(),
+            fullscreen: options.fullscreen.then_some(true),
+            // Vsync would cap both wall renderers at the refresh rate
+            vsync: options.wall_benchmark.then_some(false),
+        }
+    }
+
+    /// The settings in effect for this run.
+    pub fn apply(&self, settings: &Settings) -> Settings {
+        let mut settings = settings.clone();
+        if let Some(palette) = &self.palette {
+            settings.palette = palette.clone();
+        }
+        if let Some(fullscreen) = self.fullscreen {
+            settings.fullscreen = fullscreen;
+        }
+        if let Some(vsync) = self.vsync {
+            settings.vsync = vsync;
+        }
+        settings
+    }
+
+    /// Moves the override of `item` into the settings, so the menu adjusts the value shown.
+    fn release(&mut self, item: SettingsItem, settings: &mut Settings) {
+        match item {
+            SettingsItem::Palette => {
+                if let Some(palette) = self.palette.take() {
+                    settings.palette = palette;
+                }
+            }
+            SettingsItem::Fullscreen => {
+                if let Some(fullscreen) = self.fullscreen.take() {
+                    settings.fullscreen = fullscreen;
+                }
+            }
+            SettingsItem::Vsync => {
+                if let Some(vsync) = self.vsync.take() {
+                    settings.vsync = vsync;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SettingsItem {
+    Volume,
+    SfxVolume,
+    AmbienceVolume,
+    MusicVolume,
+    ClockSound,
+    Fullscreen,
+    Vsync,
+    ScrollSensitivity,
+    ToastPosition,
+    Palette,
+    ReducedMotion,
+    ToastScale,
+    UiScaling,
+    Announcements,
+    ExcludeSuspiciousClicks,
+    Language,
+}
+
+impl SettingsItem {
+    const ALL: [SettingsItem; 16] = [
+        SettingsItem::Volume,
+        SettingsItem::SfxVolume,
+        SettingsItem::AmbienceVolume,
+        SettingsItem::MusicVolume,
+        SettingsItem::ClockSound,
+        SettingsItem::Fullscreen,
+        SettingsItem::Vsync,
+        SettingsItem::ScrollSensitivity,
+        SettingsItem::ToastPosition,
+        SettingsItem::Palette,
+        SettingsItem::ReducedMotion,
+        SettingsItem::ToastScale,
+        SettingsItem::UiScaling,
+        SettingsItem::Announcements,
+        SettingsItem::ExcludeSuspiciousClicks,
+        SettingsItem::Language,
+    ];
+
+    /// Key of the item's name in the [`Localization`]
+    fn key(self) -> &'static str {
+        match self {
+            SettingsItem::Volume => "settings.volume",
+            SettingsItem::SfxVolume => "settings.sfx_volume",
+            SettingsItem::AmbienceVolume => "settings.ambience_volume",
+            SettingsItem::MusicVolume => "settings.music_volume",
+            SettingsItem::ClockSound => "settings.clock_sound",
+            SettingsItem::Fullscreen => "settings.fullscreen",
+            SettingsItem::Vsync => "settings.vsync",
+            SettingsItem::ScrollSensitivity => "settings.scroll_sensitivity",
+            SettingsItem::ToastPosition => "settings.toast_position",
+            SettingsItem::Palette => "settings.palette",
+            SettingsItem::ReducedMotion => "settings.reduced_motion",
+            SettingsItem::ToastScale => "settings.toast_scale",
+            SettingsItem::UiScaling => "settings.ui_scaling",
+            SettingsItem::Announcements => "settings.announcements",
+            SettingsItem::ExcludeSuspiciousClicks => "settings.exclude_suspicious_clicks",
+            SettingsItem::Language => "settings.language",
+        }
+    }
+
+    fn value(self, settings: &Settings, localization: &Localization) -> String {
+        let on_off = |on: bool| {
+            localization
+                .get(if on { "settings.on" } else { "settings.off" })
+                .to_string()
+        };
+        let named =
+            |prefix: &str, name: &str| localization.get(&format!("{prefix}.{name}")).to_string();
+        let percent = |volume: f32| format!("{:.0}%", volume * 100.0);
+        match self {
+            SettingsItem::Volume => percent(settings.volume),
+            SettingsItem::SfxVolume => percent(settings.sfx_volume),
+            SettingsItem::AmbienceVolume => percent(settings.ambience_volume),
+            SettingsItem::MusicVolume => percent(settings.music_volume),
+            SettingsItem::ClockSound => on_off(settings.clock_sound),
+            SettingsItem::Fullscreen => on_off(settings.fullscreen),
+            SettingsItem::Vsync => on_off(settings.vsync),
+            SettingsItem::ScrollSensitivity => format!("{:.2}x", settings.scroll_sensitivity),
+            SettingsItem::ToastPosition => named("toast_position", settings.toast_position.key()),
+            SettingsItem::Palette => named("palette", &settings.palette),
+            SettingsItem::ReducedMotion => on_off(settings.reduced_motion),
+            SettingsItem::ToastScale => percent(settings.toast_scale),
+            SettingsItem::UiScaling => named("ui_scaling", settings.ui_scaling.key()),
+            SettingsItem::Announcements => on_off(settings.announcements),
+            SettingsItem::ExcludeSuspiciousClicks => on_off(settings.exclude_suspicious_clicks),
+            SettingsItem::Language => named("language", &settings.language),
+        }
+    }
+
+    /// Steps the value up or down, `step` being 1 or -1.
+    fn adjust(self, settings: &mut Settings, step: i32) {
+        let cycle = |names: &[&str], current: &str| {
+            let index = names.iter().position(|name| *name == current).unwrap_or(0);
+            let len = names.len() as i32;
+            names[(index as i32 + step).rem_euclid(len) as usize].to_string()
+        };
+        let volume = |volume: &mut f32| *volume = (*volume + 0.1 * step as f32).clamp(0.0, 1.0);
+        match self {
+            SettingsItem::Volume => volume(&mut settings.volume),
+            SettingsItem::SfxVolume => volume(&mut settings.sfx_volume),
+            SettingsItem::AmbienceVolume => volume(&mut settings.ambience_volume),
+            SettingsItem::MusicVolume => volume(&mut settings.music_volume),
+            SettingsItem::ClockSound => settings.clock_sound = !settings.clock_sound,
+            SettingsItem::Fullscreen => settings.fullscreen = !settings.fullscreen,
+            SettingsItem::Vsync => settings.vsync = !settings.vsync,
+            SettingsItem::ScrollSensitivity => {
+                settings.scroll_sensitivity =
+                    (settings.scroll_sensitivity + 0.25 * step as f32).clamp(0.25, 4.0);
+            }
+            SettingsItem::ToastPosition => {
+                let index = ToastPosition::ALL
+                    .iter()
+                    .position(|position| *position == settings.toast_position)
+                    .unwrap_or(0) as i32;
+                let len = ToastPosition::ALL.len() as i32;
+                settings.toast_position =
+                    ToastPosition::ALL[(index + step).rem_euclid(len) as usize];
+            }
+            SettingsItem::Palette => {
+                settings.palette = cycle(&PALETTES.map(|(name, _, _)| name), &settings.palette);
+            }
+            SettingsItem::ReducedMotion => settings.reduced_motion = !settings.reduced_motion,
+            SettingsItem::ToastScale => {
+                settings.toast_scale = (settings.toast_scale + 0.25 * step as f32).clamp(0.5, 2.0);
+            }
+            SettingsItem::UiScaling => {
+                let index = UiScaling::ALL
+                    .iter()
+                    .position(|scaling| *scaling == settings.ui_scaling)
+                    .unwrap_or(0) as i32;
+                let len = UiScaling::ALL.len() as i32;
+                settings.ui_scaling = UiScaling::ALL[(index + step).rem_euclid(len) as usize];
+            }
+            SettingsItem::Announcements => settings.announcements = !settings.announcements,
+            SettingsItem::ExcludeSuspiciousClicks => {
+                settings.exclude_suspicious_clicks = !settings.exclude_suspicious_clicks;
+            }
+            SettingsItem::Language => settings.language = cycle(&LANGUAGES, &settings.language),
+        }
+    }
+}
+
+/// Selected row of the settings menu, shown in [`GameState::Settings`](crate::menu::GameState).
+#[derive(Resource, Default)]
+pub struct SettingsMenu {
+    selected: usize,
+}
+
+#[derive(Component)]
+pub struct SettingsPanel;
+
+pub fn setup_settings(mut commands: Commands, achievement_style: Res<AchievementStyle>) {
+    commands.init_resource::<SettingsMenu>();
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 14.0,
+                    ..achievement_style.text_style.clone()
+                },
+            ),
+            text_anchor: Anchor::Center,
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(SettingsPanel);
+}
+
+pub fn settings_menu_update(
+    mut menu: ResMut<SettingsMenu>,
+    mut settings: ResMut<Settings>,
+    mut overrides: ResMut<SettingsOverrides>,
+    keyboard_input: Res<Input<KeyCode>>,
+    localization: Res<Localization>,
+    query_ortho: Query<&OrthographicProjection>,
+    mut panel: Query<(&mut Text, &mut Transform), With<SettingsPanel>>,
+) {
+    let Ok((mut text, mut transform)) = panel.get_single_mut() else {
+        return;
+    };
+    if let Ok(ortho) = query_ortho.get_single() {
+        transform.translation = vec3(ortho.area.center().x, ortho.area.center().y, 3.0);
+    }
+
+    let items = SettingsItem::ALL.len();
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        menu.selected = (menu.selected + items - 1) % items;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        menu.selected = (menu.selected + 1) % items;
+    }
+    let item = SettingsItem::ALL[menu.selected];
+    let step = match (
+        keyboard_input.just_pressed(KeyCode::Left),
+        keyboard_input.just_pressed(KeyCode::Right),
+    ) {
+        (true, false) => -1,
+        (false, true) => 1,
+        _ => 0,
+    };
+    if step != 0 {
+        overrides.release(item, &mut settings);
+        item.adjust(&mut settings, step);
+    }
+
+    let settings = overrides.apply(&settings);
+
+    let mut lines = format!("{}\n\n", localization.get("settings.title"));
+    for (i, item) in SettingsItem::ALL.into_iter().enumerate() {
+        let cursor = if i == menu.selected { '>' } else { ' ' };
+        let name = localization.get(item.key());
+        let value = item.value(&settings, &localization);
+        lines += &format!("{cursor} {name:<22}{value}\n");
+    }
+    text.sections[0].value = lines + "\n" + localization.get("settings.footer");
+}
+
+/// Applies changed settings to the running game and writes them, without the
+/// [`SettingsOverrides`], to the settings file.
+#[allow(clippy::too_many_arguments)]
+pub fn settings_apply(
+    settings: Res<Settings>,
+    overrides: Res<SettingsOverrides>,
+    options: Res<Options>,
+    mut global_volume: ResMut<GlobalVolume>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut projections: Query<&mut OrthographicProjection>,
+    mut animation: ResMut<WallAnimationSettings>,
+    mut palette: ResMut<WallTilePalette>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !settings.is_added() && settings.is_changed() {
+        settings.save();
+    }
+    let settings = overrides.apply(&settings);
+
+    let volume = if options.mute { 0.0 } else { settings.volume };
+    if global_volume.volume.get() != volume {
+        *global_volume = GlobalVolume::new(volume);
+    }
+    for mut window in windows.iter_mut() {
+        if window.mode != settings.window_mode() {
+            window.mode = settings.window_mode();
+        }
+        if window.present_mode != settings.present_mode() {
+            window.present_mode = settings.present_mode();
+        }
+    }
+    for mut projection in projections.iter_mut() {
+        // ScalingMode can't be compared, setting it only recomputes the projection's area
+        projection.scaling_mode = settings.ui_scaling.scaling_mode();
+    }
+    if animation.enabled == settings.reduced_motion {
+        animation.enabled = !settings.reduced_motion;
+    }
+    if palette.name != settings.palette {
+        if let Some(new_palette) =
+            WallTilePalette::named(&settings.palette, palette.seed, &mut materials)
+        {
+            *palette = new_palette;
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use bevy::{math::vec3, prelude::*, sprite::Anchor};
+use std::collections::VecDeque;
+
+use crate::{
+    achievements::AchievementStyle,
+    click_analyzer::ClickAnalyzer,
+    localization::Localization,
+    profiles::ProfileSelection,
+    replay::PlayerInput,
+    storage::{load_key_values, parse_value, save_key_values},
+    ColorTemperature, LightToggled,
+};
+
+/// Play statistics, persisted across runs. Times are in seconds.
+#[derive(Resource, Default)]
+pub struct Stats {
+    pub total_toggles: u64,
+    pub session_toggles: u64,
+    pub time_light_on: f64,
+    pub time_light_off: f64,
+    /// Summed dimmer movement, where 1 is the full range
+    pub dimmer_distance: f64,
+    pub peak_toggles_per_second: u64,
+    pub sessions_played: u64,
+    pub longest_session: f64,
+    pub session_time: f64,
+    /// Session time at which the 100th session toggle happened
+    pub time_to_100: Option<f64>,
+    /// Toggles within the last second
+    recent_toggles: VecDeque<f64>,
+    last_dimmer_value: Option<f32>,
+}
+
+impl Stats {
+    pub fn load(file_name: &str) -> Self {
+        let values = load_key_values(file_name);
+        Self {
+            total_toggles: parse_value(&values, "total_toggles").unwrap_or_default(),
+            time_light_on: parse_value(&values, "time_light_on").unwrap_or_default(),
+            time_light_off: parse_value(&values, "time_light_off").unwrap_or_default(),
+            dimmer_distance: parse_value(&values, "dimmer_distance").unwrap_or_default(),
+            peak_toggles_per_second: parse_value(&values, "peak_toggles_per_second")
+                .unwrap_or_default(),
+            sessions_played: parse_value(&values, "sessions_played").unwrap_or_default(),
+            longest_session: parse_value(&values, "longest_session").unwrap_or_default(),
+            ..default()
+        }
+    }
+
+    pub fn save(&self, file_name: &str) {
+        save_key_values(
+            file_name,
+            [
+                ("total_toggles", self.total_toggles.to_string()),
+                ("time_light_on", self.time_light_on.to_string()),
+                ("time_light_off", self.time_light_off.to_string()),
+                ("dimmer_distance", self.dimmer_distance.to_string()),
+                (
+                    "peak_toggles_per_second",
+                    self.peak_toggles_per_second.to_string(),
+                ),
+                ("sessions_played", self.sessions_played.to_string()),
+                ("longest_session", self.longest_session.to_string()),
+            ],
+        );
+    }
+}
+
+#[derive(Component)]
+pub struct StatsPanel;
+
+pub fn setup_stats(mut commands: Commands, achievement_style: Res<AchievementStyle>) {
+    commands.init_resource::<Stats>();
+
+    let text_style = TextStyle {
+        font_size: 14.0,
+        ..achievement_style.text_style.clone()
+    };
+    commands
+        .spawn(Text2dBundle {
+            text: Text::from_section("", text_style),
+            text_anchor: Anchor::Center,
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(StatsPanel);
+}
+
+pub fn stats_update(
+    mut stats: ResMut<Stats>,
+    time: Res<Time>,
+    mut toggles: EventReader<LightToggled>,
+    query_light: Query<&DirectionalLight>,
+    query_temperature: Query<&ColorTemperature>,
+    click_analyzer: Res<ClickAnalyzer>,
+    player_input: Res<PlayerInput>,
+) {
+    let now = time.elapsed_seconds_f64();
+    let dt = time.delta_seconds_f64();
+    let excluded = click_analyzer.excluded(&player_input);
+
+    for toggle in toggles.read() {
+        if toggle.manual && excluded {
+            continue;
+        }
+        stats.total_toggles += 1;
+        stats.session_toggles += 1;
+        stats.recent_toggles.push_back(now);
+        if stats.session_toggles == 100 {
+            stats.time_to_100 = Some(stats.session_time);
+        }
+    }
+    while stats
+        .recent_toggles
+        .front()
+        .is_some_and(|&toggle| now - toggle > 1.0)
+    {
+        stats.recent_toggles.pop_front();
+    }
+    stats.peak_toggles_per_second = stats
+        .peak_toggles_per_second
+        .max(stats.recent_toggles.len() as u64);
+
+    if query_light.iter().any(|light| light.illuminance > 0.0) {
+        stats.time_light_on += dt;
+    } else {
+        stats.time_light_off += dt;
+    }
+
+    if let Ok(temperature) = query_temperature.get_single() {
+        if let Some(last) = stats.last_dimmer_value {
+            stats.dimmer_distance += (temperature.value - last).abs() as f64;
+        }
+        stats.last_dimmer_value = Some(temperature.value);
+    }
+
+    stats.session_time += dt;
+    stats.longest_session = stats.longest_session.max(stats.session_time);
+}
+
+pub fn format_duration(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+pub fn stats_panel_update(
+    stats: Res<Stats>,
+    keyboard_input: Res<Input<KeyCode>>,
+    selection: Option<Res<ProfileSelection>>,
+    localization: Res<Localization>,
+    query_ortho: Query<&OrthographicProjection>,
+    mut panel: Query<(&mut Text, &mut Transform, &mut Visibility), With<StatsPanel>>,
+) {
+    let Ok((mut text, mut transform, mut visibility)) = panel.get_single_mut() else {
+        return;
+    };
+    let typing = selection.is_some_and(|selection| selection.is_typing());
+    if keyboard_input.just_pressed(KeyCode::Tab) && !typing {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+    if *visibility == Visibility::Hidden {
+        return;
+    }
+    if let Ok(ortho) = query_ortho.get_single() {
+        transform.translation = vec3(ortho.area.center().x, ortho.area.center().y, 2.0);
+    }
+
+    text.sections[0].value = localization.format(
+        "stats.panel",
+        &[
+            ("total_toggles", &stats.total_toggles),
+            ("session_toggles", &stats.session_toggles),
+            ("light_on", &format_duration(stats.time_light_on)),
+            ("light_off", &format_duration(stats.time_light_off)),
+            ("dimmer_distance", &format!("{:.1}", stats.dimmer_distance)),
+            ("peak", &stats.peak_toggles_per_second),
+            ("sessions", &stats.sessions_played),
+            ("longest_session", &format_duration(stats.longest_session)),
+        ],
+    );
+}
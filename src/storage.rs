@@ -0,0 +1,63 @@
+use bevy::{prelude::*, utils::HashMap};
+use std::{fs, io, path::PathBuf};
+
+/// Directory for saved game data: `$ACA_DATA_DIR`, or a folder in the home directory.
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("ACA_DATA_DIR") {
+        return PathBuf::from(dir);
+    }
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    home.join(".aca_gamejam_winner2023")
+}
+
+/// Reads `key = value` lines. A missing file is treated as empty.
+pub fn load_key_values(file_name: &str) -> HashMap<String, String> {
+    let path = data_dir().join(file_name);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                warn!("Could not read {path:?}: {err}");
+            }
+            return HashMap::new();
+        }
+    };
+    parse_key_values(&content)
+}
+
+/// Parses `key = value` lines, skipping `#` comments.
+pub fn parse_key_values(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Writes `key = value` lines, replacing the whole file.
+pub fn save_key_values<K: AsRef<str>, V: ToString>(
+    file_name: &str,
+    values: impl IntoIterator<Item = (K, V)>,
+) {
+    let path = data_dir().join(file_name);
+    let content = values
+        .into_iter()
+        .map(|(key, value)| format!("{} = {}\n", key.as_ref(), value.to_string()))
+        .collect::<String>();
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, content));
+    if let Err(err) = result {
+        warn!("Could not write {path:?}: {err}");
+    }
+}
+
+/// Parses the value stored under `key`, if there is a valid one.
+pub fn parse_value<T: std::str::FromStr>(values: &HashMap<String, String>, key: &str) -> Option<T> {
+    values.get(key).and_then(|value| value.parse().ok())
+}
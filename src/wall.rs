@@ -0,0 +1,683 @@
+use bevy::{
+    math::vec3,
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use std::f32::consts::*;
+
+use crate::{
+    bitmap_font::BitmapFont,
+    cli::Options,
+    settings::{Settings, SettingsOverrides},
+    wall_animation::{TileFlip, WallAnimationSettings, WallTextRoll},
+    Score,
+};
+
+pub const WALL_SIZE_X: f32 = 18.0;
+pub const WALL_SIZE_Y: f32 = 5.0;
+pub const TILE_SIZE: f32 = 0.2;
+pub const WALL_TILES_X: usize = (WALL_SIZE_X / TILE_SIZE) as usize + 1;
+pub const WALL_TILES_Y: usize = (WALL_SIZE_Y / TILE_SIZE) as usize + 1;
+const PATTERN_SIZE: usize = 5;
+pub const TOP_RIGHT_DIGIT_X: usize = (WALL_SIZE_X / TILE_SIZE / 2.0) as usize + 13;
+pub const TOP_RIGHT_DIGIT_Y: usize = (WALL_SIZE_Y / TILE_SIZE / 2.0) as usize + 7;
+
+/// How the wall is drawn.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum WallRenderer {
+    /// One `PbrBundle` per tile.
+    #[default]
+    Tiles,
+    /// A single mesh with per-tile vertex colours.
+    Mesh,
+}
+
+impl WallRenderer {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "tiles" => Some(WallRenderer::Tiles),
+            "mesh" => Some(WallRenderer::Mesh),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct WallTile {
+    x: usize,
+    y: usize,
+}
+
+/// Marker for the single entity drawn by [`WallRenderer::Mesh`].
+#[derive(Component)]
+pub struct WallMesh;
+
+#[derive(Resource)]
+pub struct WallTilePalette {
+    /// One of [`PALETTES`]
+    pub name: String,
+    pub colors: Vec<Color>,
+    pub number_color: Color,
+    materials: Vec<Handle<StandardMaterial>>,
+    number_material: Handle<StandardMaterial>,
+    pub seed: u64,
+}
+
+/// Wall tile entities, indexed by `x * WALL_TILES_Y + y`.
+/// Empty unless [`WallRenderer::Tiles`] is active.
+#[derive(Resource, Default)]
+pub struct WallTiles {
+    entities: Vec<Entity>,
+}
+
+/// Palette index per pattern cell, derived from `WallTilePalette::seed`.
+#[derive(Resource)]
+pub struct WallPattern {
+    indices: Vec<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum HorizontalAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Text drawn onto the wall tiles with the number colour of the [`WallTilePalette`].
+#[derive(Component)]
+pub struct WallText {
+    pub text: String,
+    pub font: Handle<BitmapFont>,
+    /// Tile the text is aligned to, in its top row. May lie outside of the wall.
+    pub position: IVec2,
+    pub horizontal_align: HorizontalAlign,
+    pub visible: bool,
+    pub roll: Option<WallTextRoll>,
+}
+
+/// Font used for all built-in wall texts.
+#[derive(Resource)]
+pub struct WallFont {
+    pub font: Handle<BitmapFont>,
+}
+
+/// Marks the [`WallText`] showing the [`Score`].
+#[derive(Component)]
+pub struct ScoreDisplay;
+
+/// Which tiles are covered by a [`WallText`], indexed like [`WallTiles`].
+#[derive(Resource)]
+pub struct WallTextMask {
+    covered: Vec<bool>,
+    /// Tiles whose coverage changed with the last update
+    changed: Vec<usize>,
+}
+
+/// Named palettes: tile colors and the number color. The first one is the default.
+pub const PALETTES: [(&str, [&str; 3], &str); 8] = [
+    ("night", ["#0C356A", "#0174BE", "#FFC436"], "#FFF0CE"),
+    ("sunset", ["#461959", "#7A316F", "#CD6688"], "#AED8CC"),
+    ("forest", ["#163020", "#304D30", "#B6C4B6"], "#EEF0E5"),
+    ("mono", ["#222222", "#444444", "#888888"], "#FFFFFF"),
+    // Accessible palettes: dark tiles only, so the digits stand out by brightness alone, and
+    // no two tile colors that look alike with the respective color vision deficiency
+    (
+        "high_contrast",
+        ["#000000", "#101010", "#202020"],
+        "#FFFF00",
+    ),
+    ("deuteranopia", ["#0B1F3A", "#14406E", "#5A3A00"], "#FFFFFF"),
+    ("protanopia", ["#101B40", "#1F3D7A", "#4D4000"], "#FFFFFF"),
+    ("tritanopia", ["#3A0A14", "#0E4A4A", "#5C1A24"], "#FFFFFF"),
+];
+
+pub fn setup_wall(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    options: Res<Options>,
+    settings: Res<Settings>,
+    overrides: Res<SettingsOverrides>,
+) {
+    let seed = options.seed.unwrap_or_else(rand::random::<u64>);
+    let palette = WallTilePalette::named(&overrides.apply(&settings).palette, seed, &mut materials)
+        .expect("settings only name known palettes");
+    commands.insert_resource(WallPattern::new(&palette));
+    commands.insert_resource(palette);
+    commands.insert_resource(WallTextMask {
+        covered: vec![false; WALL_TILES_X * WALL_TILES_Y],
+        changed: Vec::new(),
+    });
+    commands.init_resource::<WallTiles>();
+    commands.insert_resource(options.wall_renderer);
+    commands.init_resource::<WallAnimationSettings>();
+
+    let font = asset_server.load("embedded://aca_gamejam_winner2023/wall_font.wallfont");
+    commands
+        .spawn(WallText {
+            text: String::new(),
+            font: font.clone(),
+            position: IVec2::new(TOP_RIGHT_DIGIT_X as i32, TOP_RIGHT_DIGIT_Y as i32),
+            horizontal_align: HorizontalAlign::Right,
+            visible: true,
+            roll: None,
+        })
+        .insert(ScoreDisplay);
+    commands.insert_resource(WallFont { font });
+}
+
+impl WallTilePalette {
+    /// The palette called `name` in [`PALETTES`], if there is one.
+    pub fn named(name: &str, seed: u64, materials: &mut Assets<StandardMaterial>) -> Option<Self> {
+        let (name, colors, number_color) = PALETTES.iter().find(|palette| palette.0 == name)?;
+        let colors = colors
+            .iter()
+            .map(|color| Color::hex(color).unwrap())
+            .collect::<Vec<_>>();
+        let number_color = Color::hex(number_color).unwrap();
+        Some(Self {
+            name: name.to_string(),
+            materials: colors
+                .iter()
+                .map(|&color| materials.add(color.into()))
+                .collect(),
+            number_material: materials.add(number_color.into()),
+            colors,
+            number_color,
+            seed,
+        })
+    }
+}
+
+impl WallPattern {
+    fn new(palette: &WallTilePalette) -> Self {
+        use rand::Rng;
+        use rand::SeedableRng;
+
+        // Set random seed
+        let mut rng = rand::rngs::StdRng::seed_from_u64(palette.seed);
+
+        // Clever Anke stuff: Make last color less likely than the others
+        let indices = (0..PATTERN_SIZE * PATTERN_SIZE)
+            .map(|_| (rng.gen::<usize>() % (palette.colors.len() * 2 - 1)) % palette.colors.len())
+            .collect();
+        Self { indices }
+    }
+
+    fn color_index(&self, tile: &WallTile) -> usize {
+        let x = ((tile.x % (PATTERN_SIZE * 2 - 2)) as i32 - PATTERN_SIZE as i32 + 2).unsigned_abs()
+            as usize;
+        let y = ((tile.y % (PATTERN_SIZE * 2 - 2)) as i32 - PATTERN_SIZE as i32 + 2).unsigned_abs()
+            as usize;
+        self.indices[x + y * PATTERN_SIZE]
+    }
+}
+
+pub fn tile_rotation() -> Quat {
+    Quat::from_rotation_x(FRAC_PI_2)
+}
+
+impl WallTile {
+    pub fn translation(&self) -> Vec3 {
+        tile_translation(self.x, self.y)
+    }
+}
+
+fn tile_translation(x: usize, y: usize) -> Vec3 {
+    vec3(
+        x as f32 * TILE_SIZE - WALL_SIZE_X / 2.0,
+        y as f32 * TILE_SIZE - WALL_SIZE_Y / 2.0,
+        0.0,
+    )
+}
+
+impl WallText {
+    /// Top left tile of the text, given its width in tiles.
+    fn top_left(&self, width: i32) -> IVec2 {
+        let x = match self.horizontal_align {
+            HorizontalAlign::Left => self.position.x,
+            HorizontalAlign::Center => self.position.x - width / 2,
+            HorizontalAlign::Right => self.position.x - width + 1,
+        };
+        IVec2::new(x, self.position.y)
+    }
+
+    /// Widest text that still fits onto the wall at this position.
+    fn available_width(&self) -> i32 {
+        let left = self.position.x + 1;
+        let right = WALL_TILES_X as i32 - self.position.x;
+        match self.horizontal_align {
+            HorizontalAlign::Left => right,
+            HorizontalAlign::Center => 2 * left.min(right) - 1,
+            HorizontalAlign::Right => left,
+        }
+    }
+
+    /// Which glyphs of the text and of `previous` differ from their counterpart.
+    fn roll_changes(&self, previous: &str) -> (Vec<bool>, Vec<bool>) {
+        let text = self.text.chars().collect::<Vec<_>>();
+        let previous = previous.chars().collect::<Vec<_>>();
+        // Index offset between corresponding glyphs, depending on where they line up
+        let offset = match self.horizontal_align {
+            HorizontalAlign::Left => Some(0),
+            HorizontalAlign::Right => Some(previous.len() as isize - text.len() as isize),
+            HorizontalAlign::Center => (previous.len() == text.len()).then_some(0),
+        };
+        let differs = |a: &[char], b: &[char], offset: Option<isize>| {
+            (0..a.len())
+                .map(|i| {
+                    offset.and_then(|offset| b.get((i as isize + offset) as usize)) != Some(&a[i])
+                })
+                .collect::<Vec<_>>()
+        };
+        (
+            differs(&text, &previous, offset),
+            differs(&previous, &text, offset.map(|offset| -offset)),
+        )
+    }
+}
+
+pub fn wall_pattern_update(palette: Res<WallTilePalette>, mut pattern: ResMut<WallPattern>) {
+    *pattern = WallPattern::new(&palette);
+}
+
+/// Despawns the current wall and spawns it again with the selected [`WallRenderer`].
+pub fn wall_renderer_update(
+    mut commands: Commands,
+    renderer: Res<WallRenderer>,
+    mut tiles: ResMut<WallTiles>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    existing_tiles: Query<Entity, With<WallTile>>,
+    existing_mesh: Query<Entity, With<WallMesh>>,
+) {
+    for entity in existing_tiles.iter().chain(existing_mesh.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    tiles.entities.clear();
+
+    match *renderer {
+        WallRenderer::Tiles => {
+            let mesh = meshes.add(shape::Plane::from_size(TILE_SIZE).into());
+            tiles.entities.reserve(WALL_TILES_X * WALL_TILES_Y);
+            for x in 0..WALL_TILES_X {
+                for y in 0..WALL_TILES_Y {
+                    let tile = commands
+                        .spawn(PbrBundle {
+                            mesh: mesh.clone(),
+                            // material:
+                            transform: Transform::from_rotation(tile_rotation())
+                                .with_translation(tile_translation(x, y)),
+                            ..default()
+                        })
+                        .insert(WallTile { x, y })
+                        .id();
+                    tiles.entities.push(tile);
+                }
+            }
+        }
+        WallRenderer::Mesh => {
+            commands
+                .spawn(PbrBundle {
+                    mesh: meshes.add(wall_mesh()),
+                    // Vertex colours are multiplied with the base colour
+                    material: materials.add(Color::WHITE.into()),
+                    ..default()
+                })
+                .insert(WallMesh);
+        }
+    }
+}
+
+/// One quad per tile, facing +Z, in the same layout as the tile entities.
+fn wall_mesh() -> Mesh {
+    let half = TILE_SIZE / 2.0;
+    let tile_count = WALL_TILES_X * WALL_TILES_Y;
+    let mut positions = Vec::with_capacity(tile_count * 4);
+    let mut indices = Vec::with_capacity(tile_count * 6);
+    for x in 0..WALL_TILES_X {
+        for y in 0..WALL_TILES_Y {
+            let center = tile_translation(x, y);
+            let first = positions.len() as u32;
+            positions.extend([
+                (center + vec3(-half, -half, 0.0)).to_array(),
+                (center + vec3(half, -half, 0.0)).to_array(),
+                (center + vec3(half, half, 0.0)).to_array(),
+                (center + vec3(-half, half, 0.0)).to_array(),
+            ]);
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+    let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+    let uvs = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]]
+        .into_iter()
+        .cycle()
+        .take(positions.len())
+        .collect::<Vec<[f32; 2]>>();
+    let colors = vec![[1.0, 1.0, 1.0, 1.0]; positions.len()];
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+/// Ways to write `value`, from the most to the least precise.
+/// Abbreviations are rounded down so the score is never overstated.
+fn number_formats(value: u64) -> Vec<String> {
+    const SUFFIXES: [(u64, char); 5] = [
+        (1_000, 'K'),
+        (1_000_000, 'M'),
+        (1_000_000_000, 'B'),
+        (1_000_000_000_000, 'T'),
+        (1_000_000_000_000_000, 'Q'),
+    ];
+
+    let mut formats = vec![value.to_string()];
+    if let Some(&(unit, suffix)) = SUFFIXES.iter().rev().find(|(unit, _)| value >= *unit) {
+        for decimals in (0..=3).rev() {
+            formats.push(format!("{}{suffix}", round_down(value, unit, decimals)));
+        }
+    }
+    if value >= 10 {
+        let exponent = value.ilog10();
+        for decimals in (0..=2).rev() {
+            let mantissa = round_down(value, 10u64.pow(exponent), decimals);
+            formats.push(format!("{mantissa}e{exponent}"));
+        }
+    }
+    formats
+}
+
+/// `value / unit` with `decimals` digits after the point, rounded down. Integer arithmetic, as
+/// floats would round 2300 / 1000 down to 2.29.
+fn round_down(value: u64, unit: u64, decimals: u32) -> String {
+    let scale = 10u128.pow(decimals);
+    let scaled = value as u128 * scale / unit as u128;
+    if decimals == 0 {
+        return scaled.to_string();
+    }
+    let (integer, fraction) = (scaled / scale, scaled % scale);
+    format!("{integer}.{fraction:0width$}", width = decimals as usize)
+}
+
+/// The most precise format of `value` followed by `suffix` that still fits onto the wall.
+pub fn fitting_number(font: &BitmapFont, text: &WallText, value: u64, suffix: &str) -> String {
+    let formats = number_formats(value);
+    let available_width = text.available_width();
+    let format = formats
+        .iter()
+        .find(|format| font.text_width(&format!("{format}{suffix}")) <= available_width)
+        .unwrap_or(formats.last().unwrap());
+    format!("{format}{suffix}")
+}
+
+pub fn score_display_update(
+    score: Res<Score>,
+    fonts: Res<Assets<BitmapFont>>,
+    animation: Res<WallAnimationSettings>,
+    mut score_display: Query<&mut WallText, With<ScoreDisplay>>,
+) {
+    for mut text in score_display.iter_mut() {
+        let score_str = match fonts.get(&text.font) {
+            Some(font) => fitting_number(font, &text, score.value, ""),
+            None => score.value.to_string(),
+        };
+        if text.text == score_str {
+            continue;
+        }
+        let previous = std::mem::replace(&mut text.text, score_str.clone());
+        if animation.enabled && animation.roll_duration > 0.0 && !previous.is_empty() {
+            text.roll = Some(WallTextRoll {
+                previous,
+                progress: 0.0,
+            });
+        }
+    }
+}
+
+pub fn wall_text_update(
+    texts: Query<&WallText>,
+    changed_texts: Query<(), Changed<WallText>>,
+    mut removed_texts: RemovedComponents<WallText>,
+    mut font_events: EventReader<AssetEvent<BitmapFont>>,
+    fonts: Res<Assets<BitmapFont>>,
+    mut mask: ResMut<WallTextMask>,
+) {
+    let removed = removed_texts.read().count() > 0;
+    let fonts_changed = font_events.read().count() > 0;
+    if changed_texts.is_empty() && !removed && !fonts_changed {
+        return;
+    }
+
+    let mut covered = vec![false; WALL_TILES_X * WALL_TILES_Y];
+    for text in texts.iter().filter(|text| text.visible) {
+        let Some(font) = fonts.get(&text.font) else {
+            continue;
+        };
+        let height = font.height as i32;
+        let top_left = text.top_left(font.text_width(&text.text));
+        let mut plot = |top_left: IVec2, x: i32, y: i32| {
+            let x = top_left.x + x;
+            let y = top_left.y - y;
+            if (0..WALL_TILES_X as i32).contains(&x) && (0..WALL_TILES_Y as i32).contains(&y) {
+                covered[x as usize * WALL_TILES_Y + y as usize] = true;
+            }
+        };
+
+        let Some(roll) = &text.roll else {
+            font.render(&text.text, |_, x, y| plot(top_left, x, y));
+            continue;
+        };
+        // Rows the rolling glyphs have moved up, including one blank row between them
+        let shift = (roll.progress.clamp(0.0, 1.0) * (height + 1) as f32) as i32;
+        let (text_changes, previous_changes) = text.roll_changes(&roll.previous);
+        font.render(&text.text, |i, x, y| {
+            if !text_changes[i] {
+                plot(top_left, x, y);
+            } else if y + height + 1 - shift < height {
+                plot(top_left, x, y + height + 1 - shift);
+            }
+        });
+        let previous_top_left = text.top_left(font.text_width(&roll.previous));
+        font.render(&roll.previous, |i, x, y| {
+            if previous_changes[i] && y - shift >= 0 {
+                plot(previous_top_left, x, y - shift);
+            }
+        });
+    }
+
+    let changed = (0..covered.len())
+        .filter(|&i| covered[i] != mask.covered[i])
+        .collect::<Vec<_>>();
+    if !changed.is_empty() {
+        *mask = WallTextMask { covered, changed };
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn wall_tiles_update(
+    mut commands: Commands,
+    mut wall_tiles: Query<(
+        &WallTile,
+        &mut Handle<StandardMaterial>,
+        Option<&mut TileFlip>,
+    )>,
+    tiles: Res<WallTiles>,
+    animation: Res<WallAnimationSettings>,
+    renderer: Res<WallRenderer>,
+    palette: Res<WallTilePalette>,
+    pattern: Res<WallPattern>,
+    mask: Res<WallTextMask>,
+) {
+    if *renderer != WallRenderer::Tiles {
+        return;
+    }
+
+    let tile_material = |tile: &WallTile| {
+        if mask.covered[tile.x * WALL_TILES_Y + tile.y] {
+            palette.number_material.clone()
+        } else {
+            palette.materials[pattern.color_index(tile)].clone()
+        }
+    };
+
+    if renderer.is_changed() || palette.is_changed() || pattern.is_changed() {
+        for (tile, mut material, flip) in wall_tiles.iter_mut() {
+            *material = tile_material(tile);
+            if let Some(mut flip) = flip {
+                flip.target = material.clone();
+            }
+        }
+    } else if mask.is_changed() {
+        // Only tiles under the text can have changed
+        for &i in &mask.changed {
+            let entity = tiles.entities[i];
+            let Ok((tile, mut material, flip)) = wall_tiles.get_mut(entity) else {
+                continue;
+            };
+            let new_material = tile_material(tile);
+            if let Some(mut flip) = flip {
+                flip.target = new_material;
+            } else if animation.enabled && animation.flip_duration > 0.0 {
+                commands.entity(entity).insert(TileFlip::new(new_material));
+            } else {
+                *material = new_material;
+            }
+        }
+    }
+}
+
+pub fn wall_mesh_update(
+    wall_mesh: Query<&Handle<Mesh>, With<WallMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    renderer: Res<WallRenderer>,
+    palette: Res<WallTilePalette>,
+    pattern: Res<WallPattern>,
+    mask: Res<WallTextMask>,
+) {
+    if *renderer != WallRenderer::Mesh
+        || !(renderer.is_changed()
+            || palette.is_changed()
+            || pattern.is_changed()
+            || mask.is_changed())
+    {
+        return;
+    }
+    let Some(mesh) = wall_mesh.get_single().ok().and_then(|h| meshes.get_mut(h)) else {
+        return;
+    };
+
+    let mut colors = Vec::with_capacity(WALL_TILES_X * WALL_TILES_Y * 4);
+    for x in 0..WALL_TILES_X {
+        for y in 0..WALL_TILES_Y {
+            let color = if mask.covered[x * WALL_TILES_Y + y] {
+                palette.number_color
+            } else {
+                palette.colors[pattern.color_index(&WallTile { x, y })]
+            };
+            colors.extend([color.as_linear_rgba_f32(); 4]);
+        }
+    }
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+}
+
+/// Measures both [`WallRenderer`]s back to back while the score ticks up every frame,
+/// then prints entity counts and frame times and exits.
+#[derive(Resource, Default)]
+pub struct WallBenchmark {
+    frame: usize,
+    samples: Vec<WallBenchmarkSample>,
+    frame_time_sum: f32,
+}
+
+struct WallBenchmarkSample {
+    renderer: WallRenderer,
+    entity_count: usize,
+    mean_frame_time: f32,
+}
+
+const WALL_BENCHMARK_WARMUP_FRAMES: usize = 60;
+const WALL_BENCHMARK_FRAMES: usize = 600;
+
+/// Runs in the menus, where the game time is paused. Unpauses it, so that the tile flips of the
+/// score digits play as they would during the game.
+pub fn wall_benchmark(
+    mut benchmark: ResMut<WallBenchmark>,
+    mut renderer: ResMut<WallRenderer>,
+    mut score: ResMut<Score>,
+    // The virtual time is clamped and would hide slow frames
+    time: Res<Time<Real>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    entities: Query<Entity>,
+    mut exit: EventWriter<bevy::app::AppExit>,
+) {
+    if virtual_time.is_paused() {
+        virtual_time.unpause();
+    }
+    score.value = score.value.saturating_add(1);
+    benchmark.frame += 1;
+    if benchmark.frame <= WALL_BENCHMARK_WARMUP_FRAMES {
+        return;
+    }
+    benchmark.frame_time_sum += time.delta_seconds();
+    if benchmark.frame < WALL_BENCHMARK_WARMUP_FRAMES + WALL_BENCHMARK_FRAMES {
+        return;
+    }
+
+    let sample = WallBenchmarkSample {
+        renderer: *renderer,
+        entity_count: entities.iter().count(),
+        mean_frame_time: benchmark.frame_time_sum / WALL_BENCHMARK_FRAMES as f32,
+    };
+    benchmark.samples.push(sample);
+    benchmark.frame = 0;
+    benchmark.frame_time_sum = 0.0;
+
+    match *renderer {
+        WallRenderer::Tiles => *renderer = WallRenderer::Mesh,
+        WallRenderer::Mesh => {
+            println!("{:<8} {:>10} {:>12}", "renderer", "entities", "frame [ms]");
+            for sample in &benchmark.samples {
+                println!(
+                    "{:<8} {:>10} {:>12.3}",
+                    format!("{:?}", sample.renderer),
+                    sample.entity_count,
+                    sample.mean_frame_time * 1000.0
+                );
+            }
+            exit.send(bevy::app::AppExit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abbreviations_round_down_exactly() {
+        assert_eq!(
+            number_formats(2300),
+            ["2300", "2.300K", "2.30K", "2.3K", "2K", "2.30e3", "2.3e3", "2e3"]
+        );
+        assert!(number_formats(8200).contains(&"8.20K".to_string()));
+        assert!(number_formats(1_005_000).contains(&"1.005M".to_string()));
+        assert!(number_formats(1_005_000).contains(&"1.00M".to_string()));
+        assert!(number_formats(999_999).contains(&"999.9K".to_string()));
+    }
+
+    #[test]
+    fn small_and_huge_values() {
+        assert_eq!(number_formats(7), ["7"]);
+        assert_eq!(number_formats(10), ["10", "1.00e1", "1.0e1", "1e1"]);
+        let formats = number_formats(u64::MAX);
+        assert_eq!(formats[0], "18446744073709551615");
+        assert!(formats.contains(&"18446.744Q".to_string()));
+        assert_eq!(formats.last().unwrap(), "1e19");
+    }
+}
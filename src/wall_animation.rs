@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+use std::f32::consts::*;
+
+use crate::{
+    wall::{tile_rotation, WallText, WallTile},
+    LightToggled,
+};
+
+/// Durations in seconds, distances in world units.
+/// Animations only apply to [`crate::wall::WallRenderer::Tiles`].
+#[derive(Resource)]
+pub struct WallAnimationSettings {
+    /// Disables all wall animations, tiles then switch instantly
+    pub enabled: bool,
+    /// Time for a tile to turn edge-on and back while changing its colour
+    pub flip_duration: f32,
+    /// How fast the ripple spreads from the light switch
+    pub ripple_speed: f32,
+    /// Time a single tile takes to rise and settle again
+    pub ripple_duration: f32,
+    pub ripple_height: f32,
+    /// Time for a digit to roll to the next one
+    pub roll_duration: f32,
+}
+
+impl Default for WallAnimationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            flip_duration: 0.3,
+            ripple_speed: 8.0,
+            ripple_duration: 0.4,
+            ripple_height: 0.05,
+            roll_duration: 0.4,
+        }
+    }
+}
+
+/// A tile turning around its vertical axis, switching to `target` when edge-on.
+#[derive(Component)]
+pub struct TileFlip {
+    pub target: Handle<StandardMaterial>,
+    elapsed: f32,
+}
+
+/// A tile briefly rising from the wall. Starts once `elapsed` becomes positive.
+#[derive(Component)]
+pub struct TileRipple {
+    elapsed: f32,
+}
+
+/// Odometer-like transition of a [`WallText`]: glyphs that differ from `previous`
+/// roll upwards out of view while the new ones follow from below.
+#[derive(Clone, Debug)]
+pub struct WallTextRoll {
+    pub previous: String,
+    /// From 0 to 1
+    pub progress: f32,
+}
+
+impl TileFlip {
+    pub fn new(target: Handle<StandardMaterial>) -> Self {
+        Self {
+            target,
+            elapsed: 0.0,
+        }
+    }
+}
+
+pub fn wall_tile_flip_update(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<WallAnimationSettings>,
+    mut tiles: Query<(
+        Entity,
+        &mut Transform,
+        &mut Handle<StandardMaterial>,
+        &mut TileFlip,
+    )>,
+) {
+    for (entity, mut transform, mut material, mut flip) in tiles.iter_mut() {
+        flip.elapsed += time.delta_seconds();
+        let t = if settings.enabled && settings.flip_duration > 0.0 {
+            (flip.elapsed / settings.flip_duration).min(1.0)
+        } else {
+            1.0
+        };
+        if t >= 0.5 && *material != flip.target {
+            *material = flip.target.clone();
+        }
+        let angle = (1.0 - (1.0 - 2.0 * t).abs()) * FRAC_PI_2;
+        transform.rotation = Quat::from_rotation_y(angle) * tile_rotation();
+
+        if t >= 1.0 {
+            commands.entity(entity).remove::<TileFlip>();
+        }
+    }
+}
+
+pub fn wall_ripple_start(
+    mut commands: Commands,
+    mut toggles: EventReader<LightToggled>,
+    settings: Res<WallAnimationSettings>,
+    tiles: Query<(Entity, &WallTile)>,
+) {
+    if toggles.read().count() == 0 || !settings.enabled || settings.ripple_speed <= 0.0 {
+        return;
+    }
+    // The light switch sits at the origin
+    for (entity, tile) in tiles.iter() {
+        let distance = tile.translation().truncate().length();
+        commands.entity(entity).insert(TileRipple {
+            elapsed: -distance / settings.ripple_speed,
+        });
+    }
+}
+
+pub fn wall_ripple_update(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<WallAnimationSettings>,
+    mut tiles: Query<(Entity, &mut Transform, &mut TileRipple)>,
+) {
+    for (entity, mut transform, mut ripple) in tiles.iter_mut() {
+        ripple.elapsed += time.delta_seconds();
+        if ripple.elapsed < 0.0 {
+            continue;
+        }
+        let t = ripple.elapsed / settings.ripple_duration;
+        if t >= 1.0 || !settings.enabled {
+            transform.translation.z = 0.0;
+            commands.entity(entity).remove::<TileRipple>();
+        } else {
+            transform.translation.z = settings.ripple_height * (PI * t).sin();
+        }
+    }
+}
+
+pub fn wall_text_roll_update(
+    time: Res<Time>,
+    settings: Res<WallAnimationSettings>,
+    mut texts: Query<&mut WallText>,
+) {
+    for mut text in texts.iter_mut() {
+        // Only write to rolling texts, every change redraws the wall text
+        let Some(progress) = text.roll.as_ref().map(|roll| roll.progress) else {
+            continue;
+        };
+        let progress = if settings.enabled && settings.roll_duration > 0.0 {
+            progress + time.delta_seconds() / settings.roll_duration
+        } else {
+            1.0
+        };
+        match &mut text.roll {
+            Some(roll) if progress < 1.0 => roll.progress = progress,
+            roll => *roll = None,
+        }
+    }
+}